@@ -1,6 +1,9 @@
 MEMORY {
     BOOT2 : ORIGIN = 0x10000000, LENGTH = 0x100
-    FLASH : ORIGIN = 0x10000100, LENGTH = 2048K - 0x100
+    FLASH : ORIGIN = 0x10000100, LENGTH = 2048K - 0x100 - 64K
+
+    /* Reserved for the saved device configuration, see src/storage.rs */
+    CONFIG : ORIGIN = 0x10000000 + 2048K - 64K, LENGTH = 64K
 
     /* Pick one of the two options for RAM layout     */
 
@@ -15,3 +18,7 @@ MEMORY {
     /* SCRATCH_A: ORIGIN = 0x20040000, LENGTH = 4K    */
     /* SCRATCH_B: ORIGIN = 0x20041000, LENGTH = 4K    */
 }
+
+/* Offsets of the configuration region from the start of flash */
+__config_start = ORIGIN(CONFIG) - ORIGIN(BOOT2);
+__config_end = ORIGIN(CONFIG) + LENGTH(CONFIG) - ORIGIN(BOOT2);
@@ -0,0 +1,13 @@
+//! The ranges of raw ADC samples and of the report's axes. Samples are mapped
+//! onto the axis range by [`AxisCalibration`](crate::calibration::AxisCalibration).
+
+/// Largest sample returned by the RP2040's 12-bit ADC.
+pub const ADC_MAX: u16 = 4095;
+
+/// Number of axes in [`ControlPanelReport`](crate::hid_descriptor::ControlPanelReport).
+pub const AXIS_COUNT: usize = 4;
+
+/// Smallest value allowed by the logical range in the report descriptor.
+pub const AXIS_MIN: i16 = -i16::MAX;
+/// Largest value allowed by the logical range in the report descriptor.
+pub const AXIS_MAX: i16 = i16::MAX;
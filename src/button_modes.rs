@@ -0,0 +1,188 @@
+//! How each report button behaves while its input is held.
+//!
+//! Modes work on report buttons, after remapping, so a mode follows the
+//! button the host sees wherever it's wired.
+
+use serde::{Deserialize, Serialize};
+
+use crate::inputs::BUTTON_COUNT;
+
+/// Fastest turbo rate, in presses per second. Each press and each gap lasts
+/// at least 10 ms, which games polling at 60 Hz can still see most of.
+pub const MAX_TURBO_HZ: u8 = 50;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ButtonMode {
+    /// Held while the input is held.
+    #[default]
+    Normal,
+    /// Each press turns the button on or off, for switches that latch.
+    Toggle,
+    /// Pressed and released this many times a second while the input is
+    /// held, starting with a press.
+    Turbo(u8),
+    /// Held for this many milliseconds from each press, however long the
+    /// input is held.
+    Pulse(u8),
+}
+
+impl ButtonMode {
+    pub fn is_valid(&self) -> bool {
+        match *self {
+            ButtonMode::Normal | ButtonMode::Toggle => true,
+            ButtonMode::Turbo(hz) => (1..=MAX_TURBO_HZ).contains(&hz),
+            ButtonMode::Pulse(ms) => ms > 0,
+        }
+    }
+}
+
+/// The state each mode needs between polls.
+#[derive(Clone, Copy, Debug)]
+pub struct ButtonModes {
+    held: [bool; BUTTON_COUNT],
+    latched: [bool; BUTTON_COUNT],
+    /// When each input was last pressed, in milliseconds.
+    pressed_at: [u64; BUTTON_COUNT],
+}
+
+impl ButtonModes {
+    pub const fn new() -> Self {
+        Self {
+            held: [false; BUTTON_COUNT],
+            latched: [false; BUTTON_COUNT],
+            pressed_at: [0; BUTTON_COUNT],
+        }
+    }
+
+    /// Applies each button's mode to its input, returning the buttons to
+    /// report.
+    pub fn update(
+        &mut self,
+        modes: &[ButtonMode; BUTTON_COUNT],
+        inputs: &[bool; BUTTON_COUNT],
+        now_ms: u64,
+    ) -> [bool; BUTTON_COUNT] {
+        let mut buttons = [false; BUTTON_COUNT];
+        for (i, (button, &held)) in buttons.iter_mut().zip(inputs).enumerate() {
+            let pressed = held && !self.held[i];
+            self.held[i] = held;
+            if pressed {
+                self.pressed_at[i] = now_ms;
+                if modes[i] == ButtonMode::Toggle {
+                    self.latched[i] = !self.latched[i];
+                }
+            }
+            let elapsed = now_ms.saturating_sub(self.pressed_at[i]);
+
+            *button = match modes[i] {
+                ButtonMode::Normal => held,
+                ButtonMode::Toggle => self.latched[i],
+                // Count half periods since the press; even ones are pressed.
+                ButtonMode::Turbo(hz) => held && (elapsed * 2 * hz as u64 / 1000) % 2 == 0,
+                ButtonMode::Pulse(ms) => held && elapsed < ms as u64,
+            };
+        }
+        buttons
+    }
+}
+
+impl Default for ButtonModes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs button 0 in `mode` through a held pattern, one entry per
+    /// millisecond, returning what it reported.
+    fn run(mode: ButtonMode, pattern: &[bool]) -> Vec<bool> {
+        let mut modes = [ButtonMode::Normal; BUTTON_COUNT];
+        modes[0] = mode;
+        let mut state = ButtonModes::new();
+        pattern
+            .iter()
+            .enumerate()
+            .map(|(now, &held)| {
+                let mut inputs = [false; BUTTON_COUNT];
+                inputs[0] = held;
+                state.update(&modes, &inputs, now as u64)[0]
+            })
+            .collect()
+    }
+
+    fn held_for(on: usize, off: usize) -> Vec<bool> {
+        let mut pattern = vec![true; on];
+        pattern.extend(vec![false; off]);
+        pattern
+    }
+
+    #[test]
+    fn normal_follows_the_input() {
+        let pattern = [false, true, true, false, true];
+        assert_eq!(run(ButtonMode::Normal, &pattern), pattern);
+    }
+
+    #[test]
+    fn toggle_flips_on_each_press() {
+        let pattern = [true, true, false, false, true, false, true];
+        assert_eq!(
+            run(ButtonMode::Toggle, &pattern),
+            [true, true, true, true, false, false, true]
+        );
+    }
+
+    #[test]
+    fn turbo_repeats_while_held() {
+        // 50 Hz: 10 ms pressed, 10 ms released.
+        let out = run(ButtonMode::Turbo(50), &held_for(45, 5));
+        assert!(out[..10].iter().all(|&b| b));
+        assert!(out[10..20].iter().all(|&b| !b));
+        assert!(out[20..30].iter().all(|&b| b));
+        assert!(out[45..].iter().all(|&b| !b));
+    }
+
+    #[test]
+    fn turbo_starts_each_press_pressed() {
+        let mut pattern = held_for(15, 3);
+        pattern.extend(held_for(2, 0));
+        let out = run(ButtonMode::Turbo(50), &pattern);
+        assert!(!out[14]);
+        assert!(out[18] && out[19]);
+    }
+
+    #[test]
+    fn pulse_ends_early_but_not_late() {
+        let mut pattern = held_for(30, 5);
+        pattern.extend(held_for(3, 10));
+        let out = run(ButtonMode::Pulse(20), &pattern);
+        assert!(out[..20].iter().all(|&b| b));
+        assert!(out[20..35].iter().all(|&b| !b));
+        // Let go early, and it ends early.
+        assert_eq!(&out[35..40], &[true, true, true, false, false]);
+    }
+
+    #[test]
+    fn modes_are_per_button() {
+        let mut modes = [ButtonMode::Normal; BUTTON_COUNT];
+        modes[1] = ButtonMode::Toggle;
+        let mut state = ButtonModes::new();
+        let mut inputs = [false; BUTTON_COUNT];
+        inputs[0] = true;
+        inputs[1] = true;
+        state.update(&modes, &inputs, 0);
+        let out = state.update(&modes, &[false; BUTTON_COUNT], 1);
+        assert!(!out[0]);
+        assert!(out[1]);
+    }
+
+    #[test]
+    fn rejects_bad_rates() {
+        assert!(ButtonMode::Turbo(MAX_TURBO_HZ).is_valid());
+        assert!(!ButtonMode::Turbo(0).is_valid());
+        assert!(!ButtonMode::Turbo(MAX_TURBO_HZ + 1).is_valid());
+        assert!(!ButtonMode::Pulse(0).is_valid());
+    }
+}
@@ -0,0 +1,97 @@
+#[cfg(not(feature = "button-matrix"))]
+use embassy_rp::gpio::Pull;
+use embassy_rp::gpio::{AnyPin, Input};
+use heapless::Vec;
+use usb_joystick::inputs::BUTTON_COUNT;
+#[cfg(feature = "button-matrix")]
+use {
+    embassy_rp::gpio::{Level, OutputOpenDrain, Pull},
+    embassy_time::Timer,
+    usb_joystick::matrix::{Matrix, MAX_ROWS},
+};
+
+/// Time for a column to settle after its row is driven, in microseconds.
+#[cfg(feature = "button-matrix")]
+const MATRIX_SETTLE_US: u64 = 5;
+
+/// Where the button states are read from, picked with the `button-matrix`
+/// feature.
+pub enum Buttons {
+    /// One switch per pin, in report order starting from button 1.
+    #[cfg(not(feature = "button-matrix"))]
+    Direct(Vec<Input<'static>, BUTTON_COUNT>),
+    /// Switches between row and column pins, numbered across each row.
+    #[cfg(feature = "button-matrix")]
+    Matrix {
+        rows: Vec<OutputOpenDrain<'static>, MAX_ROWS>,
+        cols: Vec<Input<'static>, BUTTON_COUNT>,
+        matrix: Matrix,
+    },
+}
+
+impl Buttons {
+    #[cfg(not(feature = "button-matrix"))]
+    pub fn direct(pins: impl IntoIterator<Item = AnyPin>) -> Self {
+        let mut inputs = Vec::new();
+        for pin in pins {
+            if inputs.push(Input::new(pin, Pull::Up)).is_err() {
+                panic!("More button pins than the report has buttons");
+            }
+        }
+        Buttons::Direct(inputs)
+    }
+
+    /// Rows are open-drain: the row being scanned is driven low and the rest
+    /// are let go, pulled up weakly. Without diodes, two switches pressed in
+    /// one column then join a low row to an idle one without shorting two
+    /// drivers together. Columns are pulled up, so each switch's diode, if it
+    /// has one, should point towards its row.
+    #[cfg(feature = "button-matrix")]
+    pub fn matrix(
+        row_pins: impl IntoIterator<Item = AnyPin>,
+        col_pins: impl IntoIterator<Item = AnyPin>,
+        diodes: bool,
+    ) -> Self {
+        let mut rows = Vec::new();
+        for pin in row_pins {
+            let mut row = OutputOpenDrain::new(pin, Level::High);
+            row.set_pullup(true);
+            if rows.push(row).is_err() {
+                panic!("Too many matrix rows");
+            }
+        }
+        let mut cols = Vec::new();
+        for pin in col_pins {
+            if cols.push(Input::new(pin, Pull::Up)).is_err() {
+                panic!("Too many matrix columns");
+            }
+        }
+        let matrix = Matrix::new(rows.len(), cols.len(), diodes);
+        Buttons::Matrix { rows, cols, matrix }
+    }
+
+    /// Reads every switch, before debouncing.
+    pub async fn read(&mut self) -> [bool; BUTTON_COUNT] {
+        match self {
+            #[cfg(not(feature = "button-matrix"))]
+            Buttons::Direct(inputs) => {
+                core::array::from_fn(|i| inputs.get(i).is_some_and(Input::is_high))
+            }
+            #[cfg(feature = "button-matrix")]
+            Buttons::Matrix { rows, cols, matrix } => {
+                let mut scan = [0; MAX_ROWS];
+                for (row, mask) in rows.iter_mut().zip(&mut scan) {
+                    row.set_low();
+                    Timer::after_micros(MATRIX_SETTLE_US).await;
+                    *mask = cols
+                        .iter()
+                        .enumerate()
+                        .fold(0, |mask, (c, col)| mask | (col.is_low() as u32) << c);
+                    row.set_high();
+                }
+                matrix.update(&scan);
+                matrix.buttons()
+            }
+        }
+    }
+}
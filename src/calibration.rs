@@ -0,0 +1,311 @@
+//! Per-axis calibration of raw ADC samples.
+//!
+//! Each axis records the raw readings at its two extremes and at rest. Samples
+//! are scaled separately on either side of the center, so a stick that rests
+//! off-center still reports zero when released and full deflection at both
+//! ends.
+
+use serde::{Deserialize, Serialize};
+
+use crate::axis::{ADC_MAX, AXIS_COUNT, AXIS_MAX, AXIS_MIN};
+
+/// Smallest distance between the center and either end that [`Capture`] will
+/// accept, in raw ADC steps. Anything less means the axis was not moved.
+pub const MIN_CAPTURE_SPAN: u16 = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AxisCalibration {
+    pub min: u16,
+    pub center: u16,
+    pub max: u16,
+    /// Distance either side of `center`, in raw ADC steps, that reads as zero.
+    pub deadzone: u16,
+}
+
+impl AxisCalibration {
+    pub const fn new() -> Self {
+        Self {
+            min: 0,
+            center: 2048,
+            max: ADC_MAX,
+            deadzone: 0,
+        }
+    }
+
+    /// Whether the points are in order and inside the ADC's range, with the
+    /// deadzone leaving some travel on both sides.
+    pub fn is_valid(&self) -> bool {
+        self.min <= self.center
+            && self.center <= self.max
+            && self.max <= ADC_MAX
+            && self.deadzone < self.center - self.min
+            && self.deadzone < self.max - self.center
+    }
+
+    /// Widest deadzone [`is_valid`](Self::is_valid) allows.
+    pub fn max_deadzone(&self) -> u16 {
+        (self.center - self.min)
+            .min(self.max - self.center)
+            .saturating_sub(1)
+    }
+
+    /// Maps a raw ADC sample onto the report's axis range.
+    pub fn apply(&self, raw: u16) -> i16 {
+        let raw = raw as i32;
+        let upper = self.center as i32 + self.deadzone as i32;
+        let lower = self.center as i32 - self.deadzone as i32;
+
+        if raw > upper {
+            let span = (self.max as i32 - upper).max(1);
+            ((raw - upper) * AXIS_MAX as i32 / span).min(AXIS_MAX as i32) as i16
+        } else if raw < lower {
+            let span = (lower - self.min as i32).max(1);
+            ((raw - lower) * AXIS_MAX as i32 / span).max(AXIS_MIN as i32) as i16
+        } else {
+            0
+        }
+    }
+}
+
+impl Default for AxisCalibration {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Calibration for every axis in the report, in report order.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Calibration {
+    pub axes: [AxisCalibration; AXIS_COUNT],
+}
+
+impl Calibration {
+    pub const fn new() -> Self {
+        Self {
+            axes: [AxisCalibration::new(); AXIS_COUNT],
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct AxisCapture {
+    min: u16,
+    center: u16,
+    max: u16,
+}
+
+impl AxisCapture {
+    fn finish(&self, deadzone: u16) -> Option<AxisCalibration> {
+        if self.center - self.min < MIN_CAPTURE_SPAN || self.max - self.center < MIN_CAPTURE_SPAN {
+            return None;
+        }
+        Some(AxisCalibration {
+            min: self.min,
+            center: self.center,
+            max: self.max,
+            deadzone,
+        })
+        .filter(AxisCalibration::is_valid)
+    }
+}
+
+/// Records the range of every axis while the user moves the stick around.
+///
+/// The first sample fed in is taken as the rest position, so capture should
+/// be started with the stick released.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Capture {
+    axes: Option<[AxisCapture; AXIS_COUNT]>,
+}
+
+impl Capture {
+    pub const fn new() -> Self {
+        Self { axes: None }
+    }
+
+    pub fn update(&mut self, raw: &[u16; AXIS_COUNT]) {
+        let axes = self.axes.get_or_insert_with(|| {
+            raw.map(|r| AxisCapture {
+                min: r,
+                center: r,
+                max: r,
+            })
+        });
+        for (axis, &r) in axes.iter_mut().zip(raw) {
+            axis.min = axis.min.min(r);
+            axis.max = axis.max.max(r);
+        }
+    }
+
+    /// Writes the captured range into `calibration`, keeping each axis'
+    /// deadzone. Axes that were not moved far enough are left untouched.
+    ///
+    /// Returns which axes were updated.
+    pub fn finish(&self, calibration: &mut Calibration) -> [bool; AXIS_COUNT] {
+        let mut updated = [false; AXIS_COUNT];
+        let Some(axes) = &self.axes else {
+            return updated;
+        };
+        for ((capture, cal), updated) in axes.iter().zip(&mut calibration.axes).zip(&mut updated) {
+            if let Some(new) = capture.finish(cal.deadzone) {
+                *cal = new;
+                *updated = true;
+            }
+        }
+        updated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SKEWED: AxisCalibration = AxisCalibration {
+        min: 300,
+        center: 2100,
+        max: 3900,
+        deadzone: 0,
+    };
+
+    #[test]
+    fn default_covers_the_whole_adc_range() {
+        let cal = AxisCalibration::default();
+        assert_eq!(cal.apply(0), AXIS_MIN);
+        assert_eq!(cal.apply(2048), 0);
+        assert_eq!(cal.apply(ADC_MAX), AXIS_MAX);
+    }
+
+    #[test]
+    fn off_center_rest_reads_zero() {
+        assert_eq!(SKEWED.apply(2100), 0);
+        assert_eq!(SKEWED.apply(300), AXIS_MIN);
+        assert_eq!(SKEWED.apply(3900), AXIS_MAX);
+    }
+
+    #[test]
+    fn sides_are_scaled_independently() {
+        let cal = AxisCalibration {
+            min: 1000,
+            center: 2000,
+            max: 4000,
+            deadzone: 0,
+        };
+        assert_eq!(cal.apply(1500), -16383);
+        assert_eq!(cal.apply(3000), 16383);
+    }
+
+    #[test]
+    fn clamps_beyond_the_calibrated_range() {
+        assert_eq!(SKEWED.apply(0), AXIS_MIN);
+        assert_eq!(SKEWED.apply(ADC_MAX), AXIS_MAX);
+    }
+
+    #[test]
+    fn deadzone_reads_zero_and_output_starts_at_its_edge() {
+        let cal = AxisCalibration {
+            deadzone: 100,
+            ..SKEWED
+        };
+        assert_eq!(cal.apply(2000), 0);
+        assert_eq!(cal.apply(2200), 0);
+        assert_eq!(cal.apply(2201), 19);
+        assert_eq!(cal.apply(1999), -19);
+        assert_eq!(cal.apply(3900), AXIS_MAX);
+        assert_eq!(cal.apply(300), AXIS_MIN);
+    }
+
+    #[test]
+    fn degenerate_calibration_does_not_divide_by_zero() {
+        let cal = AxisCalibration {
+            min: 2048,
+            center: 2048,
+            max: 2048,
+            deadzone: 0,
+        };
+        assert_eq!(cal.apply(2049), AXIS_MAX);
+        assert_eq!(cal.apply(2047), AXIS_MIN);
+    }
+
+    #[test]
+    fn validity() {
+        assert!(AxisCalibration::default().is_valid());
+        assert!(SKEWED.is_valid());
+        assert!(!AxisCalibration {
+            center: 200,
+            ..SKEWED
+        }
+        .is_valid());
+        assert!(!AxisCalibration {
+            max: 5000,
+            ..SKEWED
+        }
+        .is_valid());
+        assert!(!AxisCalibration {
+            deadzone: 1800,
+            ..SKEWED
+        }
+        .is_valid());
+    }
+
+    #[test]
+    fn capture_records_rest_and_extremes() {
+        let mut capture = Capture::new();
+        capture.update(&[2100, 1990, 2048, 0]);
+        capture.update(&[300, 4000, 2048, 0]);
+        capture.update(&[3900, 100, 2048, 0]);
+
+        let mut cal = Calibration::default();
+        cal.axes[0].deadzone = 40;
+        let updated = capture.finish(&mut cal);
+
+        assert_eq!(updated, [true, true, false, false]);
+        assert_eq!(
+            cal.axes[0],
+            AxisCalibration {
+                deadzone: 40,
+                ..SKEWED
+            }
+        );
+        assert_eq!(
+            cal.axes[1],
+            AxisCalibration {
+                min: 100,
+                center: 1990,
+                max: 4000,
+                deadzone: 0,
+            }
+        );
+        assert_eq!(cal.axes[2], AxisCalibration::default());
+    }
+
+    #[test]
+    fn capture_rejects_one_sided_movement() {
+        let mut capture = Capture::new();
+        capture.update(&[2048; AXIS_COUNT]);
+        capture.update(&[4000; AXIS_COUNT]);
+
+        let mut cal = Calibration::default();
+        assert_eq!(capture.finish(&mut cal), [false; AXIS_COUNT]);
+        assert_eq!(cal, Calibration::default());
+    }
+
+    #[test]
+    fn capture_keeps_old_calibration_when_deadzone_would_not_fit() {
+        let mut capture = Capture::new();
+        capture.update(&[2048; AXIS_COUNT]);
+        capture.update(&[1648; AXIS_COUNT]);
+        capture.update(&[2448; AXIS_COUNT]);
+
+        let mut cal = Calibration::default();
+        cal.axes[0].deadzone = 500;
+        assert_eq!(capture.finish(&mut cal), [false, true, true, true]);
+        assert_eq!(cal.axes[0].min, 0);
+    }
+
+    #[test]
+    fn finishing_without_samples_changes_nothing() {
+        let mut cal = Calibration::default();
+        assert_eq!(Capture::new().finish(&mut cal), [false; AXIS_COUNT]);
+        assert_eq!(cal, Calibration::default());
+    }
+}
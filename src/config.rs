@@ -0,0 +1,577 @@
+//! Device settings that survive a reset.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    axis::AXIS_COUNT,
+    button_modes::ButtonMode,
+    calibration::Calibration,
+    curves::Curve,
+    debounce::DebounceMode,
+    encoders::{EncoderConfig, ENCODER_COUNT},
+    filters::FilterConfig,
+    hat::HatConfig,
+    hid_descriptor::ReportLayout,
+    host_reports::LED_COUNT,
+    inputs::BUTTON_COUNT,
+    led_patterns::Pattern,
+    pixels::PixelConfig,
+    profiles::{Profile, ProfileCombo, ProfileName, PROFILE_COUNT},
+    remap::ButtonMap,
+    sensors::SensorConfig,
+    sources::AxisSource,
+    virtual_controls::{AxisButton, ButtonAxis, AXIS_BUTTON_COUNT, BUTTON_AXIS_COUNT},
+};
+
+/// Bumped whenever the layout of [`Config`] changes, so that records written
+/// by older firmware are ignored rather than misread.
+pub const CONFIG_VERSION: u16 = 19;
+
+/// Slowest report interval that can be configured, in milliseconds.
+pub const MAX_POLL_MS: u8 = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Config {
+    pub power: bool,
+    /// Axes whose direction is reversed, in report order.
+    pub invert: [bool; AXIS_COUNT],
+    /// Time between reports, in milliseconds.
+    pub poll_ms: u8,
+    pub calibration: Calibration,
+    pub debounce: DebounceMode,
+    /// How long each button must settle for, in milliseconds.
+    pub debounce_ms: [u8; BUTTON_COUNT],
+    /// What the status LEDs show when the host leaves them alone.
+    pub led_pattern: Pattern,
+    /// How bright each LED is when fully lit, from 0 to 255.
+    pub led_brightness: [u8; LED_COUNT],
+    /// The strip of WS2812 pixels, if one is fitted.
+    pub pixels: PixelConfig,
+    /// Where the hat switch is read from, if anywhere.
+    pub hat: HatConfig,
+    /// Whether the dial and wheel are in the report follows these at
+    /// startup, like [`Config::axis_sources`].
+    pub encoders: [EncoderConfig; ENCODER_COUNT],
+    /// The sensor on the I2C bus.
+    pub sensor: SensorConfig,
+    /// What drives each axis, in report order. Read on every poll, but the
+    /// descriptor is built from this at startup, so adding or removing an
+    /// axis takes effect after a restart.
+    pub axis_sources: [AxisSource; AXIS_COUNT],
+    /// Smoothing for each axis's samples, in report order.
+    pub filters: [FilterConfig; AXIS_COUNT],
+    /// How each axis responds across its travel, in report order.
+    pub curves: [Curve; AXIS_COUNT],
+    /// Which report button each physical button sends. The hat and the
+    /// encoders' pulses work on report buttons, after this is applied.
+    pub button_map: ButtonMap,
+    /// How each report button behaves, applied after the button map.
+    pub button_modes: [ButtonMode; BUTTON_COUNT],
+    /// Buttons pressed while an axis is past a threshold, on top of the
+    /// buttons above.
+    pub axis_buttons: [AxisButton; AXIS_BUTTON_COUNT],
+    /// Axes driven by a pair of report buttons, for
+    /// [`AxisSource::Buttons`] to name.
+    pub button_axes: [ButtonAxis; BUTTON_AXIS_COUNT],
+    /// Which of [`Config::profiles`] the settings above belong to.
+    pub active_profile: u8,
+    /// Held together, switches to the next profile.
+    pub profile_combo: ProfileCombo,
+    /// Saved sets of settings, `None` until first switched away from. The
+    /// active one is only brought up to date when switching away from it, so
+    /// the settings above are the ones in use. These can be left out of a
+    /// config sent to the web API, which keeps them as they were anyway.
+    #[serde(default)]
+    pub profiles: [Option<Profile>; PROFILE_COUNT],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfigError {
+    /// The calibration of the axis at this index is out of order or range.
+    Calibration(usize),
+    PollRate,
+    LedPattern,
+    Pixels,
+    Hat,
+    /// The encoder at this index names a missing button or has no pulse
+    /// width.
+    Encoder(usize),
+    Sensor,
+    /// The source of the axis at this index doesn't exist.
+    AxisSource(usize),
+    /// The filter windows of the axis at this index are out of range.
+    Filter(usize),
+    /// The curve of the axis at this index is out of range or out of order.
+    Curve(usize),
+    ButtonMap,
+    /// The mode of the report button at this index is out of range.
+    ButtonMode(usize),
+    AxisButton(usize),
+    ButtonAxis(usize),
+    ActiveProfile,
+    ProfileCombo,
+    /// The profile at this index holds settings that are out of range.
+    Profile(usize),
+    /// A profile would add or remove axes from the report.
+    ReportLayout,
+}
+
+impl ConfigError {
+    pub fn message(&self) -> &'static str {
+        match self {
+            ConfigError::Calibration(_) => {
+                "calibration points must be in order, with room for the deadzone"
+            }
+            ConfigError::PollRate => "poll_ms must be between 1 and 100",
+            ConfigError::LedPattern => "led_pattern must name an existing axis or a non-zero code",
+            ConfigError::Pixels => "pixels must name existing inputs, with at most 8 fitted",
+            ConfigError::Hat => "hat must name existing buttons and axes",
+            ConfigError::Encoder(_) => {
+                "encoders must name existing buttons, with a pulse_ms above 0"
+            }
+            ConfigError::Sensor => "sensor address must be 0 or between 0x08 and 0x77",
+            ConfigError::AxisSource(_) => "axis_sources must name existing inputs",
+            ConfigError::Filter(_) => {
+                "filter windows must be from 1 to 8, with an odd median window"
+            }
+            ConfigError::Curve(_) => {
+                "curves need a strength of at most 100, or 2 to 16 points in order"
+            }
+            ConfigError::ButtonMap => "button_map must name existing buttons, or 255 for none",
+            ConfigError::ButtonMode(_) => {
+                "button_modes need a turbo rate of 1 to 50 and a pulse above 0"
+            }
+            ConfigError::AxisButton(_) => {
+                "axis_buttons must name existing axes and buttons, or 255 for none"
+            }
+            ConfigError::ButtonAxis(_) => "button_axes must name existing buttons",
+            ConfigError::ActiveProfile => "active_profile must name an existing profile",
+            ConfigError::ProfileCombo => "profile_combo must name up to 3 existing buttons",
+            ConfigError::Profile(_) => "profiles must hold valid settings",
+            ConfigError::ReportLayout => {
+                "axis_sources and encoders must keep the axes the device started with"
+            }
+        }
+    }
+}
+
+impl Config {
+    pub const fn new() -> Self {
+        Self {
+            power: true,
+            // The X and Z potentiometers are mounted the other way around.
+            invert: [true, false, true, false],
+            poll_ms: 1,
+            calibration: Calibration::new(),
+            debounce: DebounceMode::Integrator,
+            debounce_ms: [5; BUTTON_COUNT],
+            led_pattern: Pattern::BinaryCounter,
+            led_brightness: [255; LED_COUNT],
+            pixels: PixelConfig::new(),
+            hat: HatConfig::new(),
+            encoders: [EncoderConfig::new(); ENCODER_COUNT],
+            sensor: SensorConfig::new(),
+            // The three potentiometers on GPIO 26 to 28.
+            axis_sources: [
+                AxisSource::Adc(0),
+                AxisSource::Adc(1),
+                AxisSource::Adc(2),
+                AxisSource::None,
+            ],
+            filters: [FilterConfig::new(); AXIS_COUNT],
+            curves: [Curve::Linear; AXIS_COUNT],
+            button_map: ButtonMap::new(),
+            button_modes: [ButtonMode::Normal; BUTTON_COUNT],
+            axis_buttons: [AxisButton::new(); AXIS_BUTTON_COUNT],
+            button_axes: [ButtonAxis::new(); BUTTON_AXIS_COUNT],
+            active_profile: 0,
+            profile_combo: ProfileCombo::new(),
+            profiles: [None; PROFILE_COUNT],
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if !(1..=MAX_POLL_MS).contains(&self.poll_ms) {
+            return Err(ConfigError::PollRate);
+        }
+        if !self.led_pattern.is_valid() {
+            return Err(ConfigError::LedPattern);
+        }
+        if !self.pixels.is_valid() {
+            return Err(ConfigError::Pixels);
+        }
+        if !self.hat.is_valid() {
+            return Err(ConfigError::Hat);
+        }
+        if let Some(encoder) = self.encoders.iter().position(|e| !e.is_valid()) {
+            return Err(ConfigError::Encoder(encoder));
+        }
+        if !self.sensor.is_valid() {
+            return Err(ConfigError::Sensor);
+        }
+        if let Some(axis) = self.axis_sources.iter().position(|s| !s.is_valid()) {
+            return Err(ConfigError::AxisSource(axis));
+        }
+        if let Some(axis) = self.filters.iter().position(|f| !f.is_valid()) {
+            return Err(ConfigError::Filter(axis));
+        }
+        if let Some(axis) = self.curves.iter().position(|c| !c.is_valid()) {
+            return Err(ConfigError::Curve(axis));
+        }
+        if !self.button_map.is_valid() {
+            return Err(ConfigError::ButtonMap);
+        }
+        if let Some(button) = self.button_modes.iter().position(|m| !m.is_valid()) {
+            return Err(ConfigError::ButtonMode(button));
+        }
+        if let Some(button) = self.axis_buttons.iter().position(|b| !b.is_valid()) {
+            return Err(ConfigError::AxisButton(button));
+        }
+        if let Some(axis) = self.button_axes.iter().position(|a| !a.is_valid()) {
+            return Err(ConfigError::ButtonAxis(axis));
+        }
+        if self.active_profile as usize >= PROFILE_COUNT {
+            return Err(ConfigError::ActiveProfile);
+        }
+        if !self.profile_combo.is_valid() {
+            return Err(ConfigError::ProfileCombo);
+        }
+        if let Some(profile) = self
+            .profiles
+            .iter()
+            .position(|p| p.is_some_and(|p| !p.is_valid()))
+        {
+            return Err(ConfigError::Profile(profile));
+        }
+        match self
+            .calibration
+            .axes
+            .iter()
+            .position(|axis| !axis.is_valid())
+        {
+            Some(axis) => Err(ConfigError::Calibration(axis)),
+            None => Ok(()),
+        }
+    }
+
+    /// Which axes go in the report: those with a source, and the relative
+    /// ones an encoder drives.
+    pub fn report_layout(&self) -> ReportLayout {
+        ReportLayout::of(&self.axis_sources, &self.encoders)
+    }
+
+    /// The name of the profile at `index`, which must exist.
+    pub fn profile_name(&self, index: usize) -> ProfileName {
+        match &self.profiles[index] {
+            Some(profile) => profile.name,
+            None => ProfileName::numbered(index),
+        }
+    }
+
+    /// The profile at `index`, which for the active one is the settings in
+    /// use.
+    pub fn profile(&self, index: usize) -> Option<Profile> {
+        if index == self.active_profile as usize {
+            return Some(Profile::capture(self.profile_name(index), self));
+        }
+        *self.profiles.get(index)?
+    }
+
+    /// Saves the settings in use to the active profile and loads the one at
+    /// `index`. A profile that has never been saved starts as a copy of the
+    /// settings in use. `layout` is the report's layout, which the profile
+    /// must keep.
+    pub fn switch_profile(
+        &mut self,
+        index: usize,
+        layout: &ReportLayout,
+    ) -> Result<(), ConfigError> {
+        if index >= PROFILE_COUNT {
+            return Err(ConfigError::ActiveProfile);
+        }
+        if let Some(profile) = self.profiles[index] {
+            if profile.report_layout() != *layout {
+                return Err(ConfigError::ReportLayout);
+            }
+        }
+        let active = self.active_profile as usize;
+        self.profiles[active] = Some(Profile::capture(self.profile_name(active), self));
+        if let Some(profile) = self.profiles[index] {
+            profile.apply(self);
+        }
+        self.active_profile = index as u8;
+        Ok(())
+    }
+
+    /// Replaces the profile at `index`, and the settings in use if it's the
+    /// active one. Like [`Config::switch_profile`], the profile must keep the
+    /// report's `layout`.
+    pub fn set_profile(
+        &mut self,
+        index: usize,
+        profile: Profile,
+        layout: &ReportLayout,
+    ) -> Result<(), ConfigError> {
+        if index >= PROFILE_COUNT {
+            return Err(ConfigError::ActiveProfile);
+        }
+        if !profile.is_valid() {
+            return Err(ConfigError::Profile(index));
+        }
+        if profile.report_layout() != *layout {
+            return Err(ConfigError::ReportLayout);
+        }
+        if index == self.active_profile as usize {
+            profile.apply(self);
+        }
+        self.profiles[index] = Some(profile);
+        Ok(())
+    }
+
+    /// Calibrates a raw sample for the axis at `index`, then applies its
+    /// inversion and curve.
+    pub fn map_axis(&self, index: usize, raw: u16) -> i16 {
+        let value = self.calibration.axes[index].apply(raw);
+        let value = if self.invert[index] { -value } else { value };
+        self.curves[index].apply(value)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        axis::{AXIS_MAX, AXIS_MIN},
+        curves::{CurvePoint, CurvePoints},
+        encoders::EncoderMode,
+    };
+
+    /// The layout the default config starts with.
+    fn layout() -> ReportLayout {
+        Config::default().report_layout()
+    }
+
+    #[test]
+    fn default_is_valid() {
+        assert_eq!(Config::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn rejects_bad_poll_rate() {
+        let mut config = Config::default();
+        config.poll_ms = 0;
+        assert_eq!(config.validate(), Err(ConfigError::PollRate));
+        config.poll_ms = MAX_POLL_MS + 1;
+        assert_eq!(config.validate(), Err(ConfigError::PollRate));
+    }
+
+    #[test]
+    fn rejects_deadzone_wider_than_travel() {
+        let mut config = Config::default();
+        config.calibration.axes[1].deadzone = 3000;
+        assert_eq!(config.validate(), Err(ConfigError::Calibration(1)));
+    }
+
+    #[test]
+    fn inverted_axes_are_mirrored() {
+        let mut config = Config::default();
+        config.invert = [true, false, false, false];
+        assert_eq!(config.map_axis(0, 0), i16::MAX);
+        assert_eq!(config.map_axis(0, 4095), -i16::MAX);
+        assert_eq!(config.map_axis(1, 0), -i16::MAX);
+        assert_eq!(config.map_axis(1, 4095), i16::MAX);
+    }
+
+    #[test]
+    fn rejects_bad_led_pattern() {
+        let mut config = Config::default();
+        config.led_pattern = Pattern::AxisBar(AXIS_COUNT as u8);
+        assert_eq!(config.validate(), Err(ConfigError::LedPattern));
+    }
+
+    #[test]
+    fn rejects_bad_filter() {
+        let mut config = Config::default();
+        config.filters[3].median = 4;
+        assert_eq!(config.validate(), Err(ConfigError::Filter(3)));
+    }
+
+    #[test]
+    fn rejects_bad_curve() {
+        let mut config = Config::default();
+        config.curves[1] = Curve::Expo(101);
+        assert_eq!(config.validate(), Err(ConfigError::Curve(1)));
+    }
+
+    #[test]
+    fn curve_follows_inversion() {
+        let mut config = Config::default();
+        config.invert[0] = true;
+        config.curves[0] = Curve::Points(CurvePoints::new(&[
+            CurvePoint::new(AXIS_MIN, AXIS_MIN),
+            CurvePoint::new(0, AXIS_MAX),
+        ]));
+        // Fully one way is fully the other once inverted, which the curve
+        // then holds at the top.
+        assert_eq!(config.map_axis(0, 0), AXIS_MAX);
+        assert_eq!(config.map_axis(0, 4095), AXIS_MIN);
+    }
+
+    #[test]
+    fn switching_profiles_keeps_each_ones_settings() {
+        let mut config = Config::default();
+        config.curves[0] = Curve::Expo(30);
+        config.led_pattern = Pattern::Chase;
+
+        // A fresh profile starts as a copy of the one left.
+        config.switch_profile(2, &layout()).unwrap();
+        assert_eq!(config.active_profile, 2);
+        assert_eq!(config.curves[0], Curve::Expo(30));
+        config.curves[0] = Curve::SCurve(60);
+        config.led_pattern = Pattern::Breathe;
+
+        config.switch_profile(0, &layout()).unwrap();
+        assert_eq!(config.curves[0], Curve::Expo(30));
+        assert_eq!(config.led_pattern, Pattern::Chase);
+        config.switch_profile(2, &layout()).unwrap();
+        assert_eq!(config.curves[0], Curve::SCurve(60));
+        assert_eq!(config.led_pattern, Pattern::Breathe);
+        assert_eq!(config.validate(), Ok(()));
+    }
+
+    #[test]
+    fn switching_leaves_shared_settings_alone() {
+        let mut config = Config::default();
+        config.switch_profile(1, &layout()).unwrap();
+        config.poll_ms = 8;
+        config.calibration.axes[0].center = 2100;
+        config.calibration.axes[0].deadzone = 30;
+        config.switch_profile(0, &layout()).unwrap();
+        assert_eq!(config.poll_ms, 8);
+        assert_eq!(config.calibration.axes[0].center, 2100);
+        assert_eq!(config.calibration.axes[0].deadzone, 0);
+    }
+
+    #[test]
+    fn profiles_can_move_an_axis_to_another_source() {
+        let mut config = Config::default();
+        config.switch_profile(1, &layout()).unwrap();
+        config.axis_sources[0] = AxisSource::Adc(3);
+        config.encoders[0].mode = EncoderMode::Buttons;
+        config.switch_profile(0, &layout()).unwrap();
+        assert_eq!(config.axis_sources[0], AxisSource::Adc(0));
+        assert_eq!(config.encoders[0].mode, EncoderMode::Off);
+        config.switch_profile(1, &layout()).unwrap();
+        assert_eq!(config.axis_sources[0], AxisSource::Adc(3));
+        assert_eq!(config.encoders[0].mode, EncoderMode::Buttons);
+    }
+
+    #[test]
+    fn profiles_that_change_the_report_layout_are_refused() {
+        let mut config = Config::default();
+        let mut profile = config.profile(0).unwrap();
+        profile.axis_sources[3] = AxisSource::Adc(3);
+        assert_eq!(
+            config.set_profile(0, profile, &layout()),
+            Err(ConfigError::ReportLayout)
+        );
+        let mut profile = config.profile(0).unwrap();
+        profile.encoders[1].mode = EncoderMode::Wheel;
+        assert_eq!(
+            config.set_profile(1, profile, &layout()),
+            Err(ConfigError::ReportLayout)
+        );
+        assert_eq!(config, Config::default());
+
+        // One saved by firmware that started with other axes.
+        config.profiles[2] = Some(profile);
+        assert_eq!(
+            config.switch_profile(2, &layout()),
+            Err(ConfigError::ReportLayout)
+        );
+        assert_eq!(config.active_profile, 0);
+        assert_eq!(config.report_layout(), layout());
+    }
+
+    #[test]
+    fn setting_the_active_profile_applies_it() {
+        let mut config = Config::default();
+        let mut profile = config.profile(0).unwrap();
+        assert_eq!(profile.name.as_str(), "Profile 1");
+        profile.name = ProfileName::new("Racing").unwrap();
+        profile.invert = [false; AXIS_COUNT];
+        config.set_profile(0, profile, &layout()).unwrap();
+        assert_eq!(config.invert, [false; AXIS_COUNT]);
+        assert_eq!(config.profile_name(0).as_str(), "Racing");
+
+        profile.curves[3] = Curve::Expo(200);
+        assert_eq!(
+            config.set_profile(1, profile, &layout()),
+            Err(ConfigError::Profile(1))
+        );
+        assert_eq!(
+            config.switch_profile(PROFILE_COUNT, &layout()),
+            Err(ConfigError::ActiveProfile)
+        );
+    }
+
+    #[test]
+    fn rejects_bad_button_map() {
+        let mut config = Config::default();
+        config.button_map.shifts[0].map[2] = 40;
+        assert_eq!(config.validate(), Err(ConfigError::ButtonMap));
+    }
+
+    #[test]
+    fn rejects_bad_button_mode() {
+        let mut config = Config::default();
+        config.button_modes[9] = ButtonMode::Turbo(0);
+        assert_eq!(config.validate(), Err(ConfigError::ButtonMode(9)));
+    }
+
+    #[test]
+    fn rejects_bad_virtual_controls() {
+        let mut config = Config::default();
+        config.axis_buttons[2].axis = AXIS_COUNT as u8;
+        assert_eq!(config.validate(), Err(ConfigError::AxisButton(2)));
+
+        let mut config = Config::default();
+        config.button_axes[1].buttons[0] = BUTTON_COUNT as u8;
+        assert_eq!(config.validate(), Err(ConfigError::ButtonAxis(1)));
+    }
+
+    #[test]
+    fn rejects_bad_profiles() {
+        let mut config = Config::default();
+        config.active_profile = PROFILE_COUNT as u8;
+        assert_eq!(config.validate(), Err(ConfigError::ActiveProfile));
+
+        let mut config = Config::default();
+        let mut profile = config.profile(0).unwrap();
+        profile.hat.buttons[0] = 200;
+        config.profiles[3] = Some(profile);
+        assert_eq!(config.validate(), Err(ConfigError::Profile(3)));
+    }
+
+    #[test]
+    fn layout_leaves_out_axes_without_a_source() {
+        let mut config = Config::default();
+        config.encoders[1].mode = EncoderMode::Wheel;
+        let layout = config.report_layout();
+        assert_eq!(layout.axes, [true, true, true, false]);
+        assert_eq!(layout.relative, [false, true]);
+    }
+
+    #[test]
+    fn rejects_bad_calibration() {
+        let mut config = Config::default();
+        config.calibration.axes[2].min = 3000;
+        assert_eq!(config.validate(), Err(ConfigError::Calibration(2)));
+    }
+}
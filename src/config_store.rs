@@ -0,0 +1,442 @@
+//! Wear-levelled storage of [`Config`] records in NOR flash.
+//!
+//! The reserved region is split into fixed-size slots. Each save goes into
+//! the slots after the newest record, taking as many as it needs but never
+//! running over the end of a sector, and a sector is only erased when the
+//! writes roll over into it, so erases are spread evenly across the region.
+//! Every record carries a sequence number and a CRC; on load, the newest
+//! record that passes its checks wins.
+
+use embedded_storage::nor_flash::NorFlash;
+
+use crate::config::{Config, CONFIG_VERSION};
+
+/// Size of one record slot. Must divide the flash erase size.
+pub const SLOT_SIZE: usize = 1024;
+
+/// Longest record, header included. A config with every profile saved takes
+/// a few slots. Must be no more than the flash erase size.
+pub const MAX_RECORD_SIZE: usize = 4096;
+
+const MAGIC: u32 = u32::from_le_bytes(*b"JCFG");
+
+// Record layout, all little-endian:
+//   0..4   magic
+//   4..8   CRC-32 of bytes 8..HEADER_SIZE + len
+//   8..10  config version
+//   10..12 payload length
+//   12..16 sequence number
+const HEADER_SIZE: usize = 16;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum StoreError<E> {
+    Flash(E),
+    /// The config did not fit in [`MAX_RECORD_SIZE`].
+    Encode,
+}
+
+pub struct ConfigStore<F> {
+    flash: F,
+    start: u32,
+    slots: u32,
+    next_slot: u32,
+    seq: u32,
+}
+
+impl<F: NorFlash> ConfigStore<F> {
+    /// Uses the flash between `start` and `end`, which must both be aligned
+    /// to the erase size.
+    pub fn new(flash: F, start: u32, end: u32) -> Self {
+        assert!(F::ERASE_SIZE % SLOT_SIZE == 0 && F::ERASE_SIZE >= MAX_RECORD_SIZE);
+        assert!(start as usize % F::ERASE_SIZE == 0 && end as usize % F::ERASE_SIZE == 0);
+        assert!(end > start);
+
+        Self {
+            flash,
+            start,
+            slots: (end - start) / SLOT_SIZE as u32,
+            next_slot: 0,
+            seq: 0,
+        }
+    }
+
+    /// Finds the newest valid record, or `None` if there isn't one.
+    pub fn load(&mut self) -> Option<Config> {
+        let mut newest: Option<(u32, u32, Config)> = None;
+        let mut slot = 0;
+        while slot < self.slots {
+            let Some((seq, used, config)) = self.read_record(slot) else {
+                slot += 1;
+                continue;
+            };
+            slot += used;
+            if newest.is_none_or(|(newest_seq, _, _)| seq > newest_seq) {
+                newest = Some((seq, slot, config));
+            }
+        }
+
+        let (seq, end, config) = newest?;
+        self.seq = seq;
+        self.next_slot = end % self.slots;
+        Some(config)
+    }
+
+    pub fn save(&mut self, config: &Config) -> Result<(), StoreError<F::Error>> {
+        let mut buf = [0xff; MAX_RECORD_SIZE];
+        let len = postcard::to_slice(config, &mut buf[HEADER_SIZE..])
+            .map_err(|_| StoreError::Encode)?
+            .len();
+        let seq = self.seq.wrapping_add(1);
+
+        buf[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+        buf[8..10].copy_from_slice(&CONFIG_VERSION.to_le_bytes());
+        buf[10..12].copy_from_slice(&(len as u16).to_le_bytes());
+        buf[12..16].copy_from_slice(&seq.to_le_bytes());
+        let crc = crc32(&buf[8..HEADER_SIZE + len]);
+        buf[4..8].copy_from_slice(&crc.to_le_bytes());
+
+        let used = slots_for(len);
+        let slot = self.prepare_slots(used)?;
+        let size = (HEADER_SIZE + len).next_multiple_of(F::WRITE_SIZE);
+        self.flash
+            .write(self.slot_offset(slot), &buf[..size])
+            .map_err(StoreError::Flash)?;
+
+        self.seq = seq;
+        self.next_slot = (slot + used) % self.slots;
+        Ok(())
+    }
+
+    /// Picks the first of `count` slots for the next record, erasing their
+    /// sector if the writes are moving into a new one.
+    fn prepare_slots(&mut self, count: u32) -> Result<u32, StoreError<F::Error>> {
+        let slots_per_sector = (F::ERASE_SIZE / SLOT_SIZE) as u32;
+        let mut slot = self.next_slot;
+
+        // Either the record doesn't fit in what's left of this sector, or
+        // something other than a clean record was left here, most likely by a
+        // write that was cut short. Move on to the next sector rather than
+        // erasing this one, which may still hold the newest record.
+        if slot % slots_per_sector != 0
+            && (slot % slots_per_sector + count > slots_per_sector
+                || !self.is_blank(slot, count)?)
+        {
+            slot = (slot / slots_per_sector + 1) * slots_per_sector % self.slots;
+        }
+
+        if slot % slots_per_sector == 0 {
+            let from = self.slot_offset(slot);
+            self.flash
+                .erase(from, from + F::ERASE_SIZE as u32)
+                .map_err(StoreError::Flash)?;
+        }
+
+        Ok(slot)
+    }
+
+    fn is_blank(&mut self, slot: u32, count: u32) -> Result<bool, StoreError<F::Error>> {
+        let mut buf = [0; SLOT_SIZE];
+        for slot in slot..slot + count {
+            self.flash
+                .read(self.slot_offset(slot), &mut buf)
+                .map_err(StoreError::Flash)?;
+            if buf.iter().any(|&b| b != 0xff) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// The record starting at `slot`, with the number of slots it takes.
+    fn read_record(&mut self, slot: u32) -> Option<(u32, u32, Config)> {
+        let mut buf = [0; MAX_RECORD_SIZE];
+        self.flash
+            .read(self.slot_offset(slot), &mut buf[..HEADER_SIZE])
+            .ok()?;
+
+        let field = |range: core::ops::Range<usize>| &buf[range];
+        let magic = u32::from_le_bytes(field(0..4).try_into().unwrap());
+        let crc = u32::from_le_bytes(field(4..8).try_into().unwrap());
+        let version = u16::from_le_bytes(field(8..10).try_into().unwrap());
+        let len = u16::from_le_bytes(field(10..12).try_into().unwrap()) as usize;
+        let seq = u32::from_le_bytes(field(12..16).try_into().unwrap());
+
+        if magic != MAGIC || version != CONFIG_VERSION || len > MAX_RECORD_SIZE - HEADER_SIZE {
+            return None;
+        }
+        // Records never run over the end of a sector.
+        let used = slots_for(len);
+        let slots_per_sector = (F::ERASE_SIZE / SLOT_SIZE) as u32;
+        if slot % slots_per_sector + used > slots_per_sector {
+            return None;
+        }
+        self.flash
+            .read(
+                self.slot_offset(slot) + HEADER_SIZE as u32,
+                &mut buf[HEADER_SIZE..HEADER_SIZE + len],
+            )
+            .ok()?;
+        if crc32(&buf[8..HEADER_SIZE + len]) != crc {
+            return None;
+        }
+
+        let config: Config = postcard::from_bytes(&buf[HEADER_SIZE..HEADER_SIZE + len]).ok()?;
+        config.validate().ok()?;
+        Some((seq, used, config))
+    }
+
+    fn slot_offset(&self, slot: u32) -> u32 {
+        self.start + slot * SLOT_SIZE as u32
+    }
+}
+
+/// Number of slots a record with a `len` byte payload takes.
+fn slots_for(len: usize) -> u32 {
+    (HEADER_SIZE + len).div_ceil(SLOT_SIZE) as u32
+}
+
+/// CRC-32 (IEEE), computed bitwise to avoid spending flash on a table.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        axis::{AXIS_COUNT, AXIS_MIN},
+        button_modes::{ButtonMode, MAX_TURBO_HZ},
+        curves::{Curve, CurvePoint, CurvePoints, MAX_CURVE_POINTS},
+        inputs::BUTTON_COUNT,
+        pixels::MAX_PIXELS,
+        profiles::{ProfileName, PROFILE_COUNT},
+        virtual_controls::AXIS_BUTTON_COUNT,
+    };
+    use embedded_storage::nor_flash::{ErrorType, NorFlashErrorKind, ReadNorFlash};
+
+    const SECTOR: usize = 4096;
+    const SECTORS: usize = 4;
+
+    /// RAM-backed flash that, like the real thing, can only clear bits when
+    /// writing.
+    struct MockFlash {
+        data: [u8; SECTOR * SECTORS],
+        erases: [u32; SECTORS],
+    }
+
+    impl MockFlash {
+        fn new() -> Self {
+            Self {
+                data: [0xff; SECTOR * SECTORS],
+                erases: [0; SECTORS],
+            }
+        }
+    }
+
+    impl ErrorType for MockFlash {
+        type Error = NorFlashErrorKind;
+    }
+
+    impl ReadNorFlash for MockFlash {
+        const READ_SIZE: usize = 1;
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            let offset = offset as usize;
+            bytes.copy_from_slice(&self.data[offset..offset + bytes.len()]);
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            self.data.len()
+        }
+    }
+
+    impl NorFlash for MockFlash {
+        const WRITE_SIZE: usize = 1;
+        const ERASE_SIZE: usize = SECTOR;
+
+        fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+            let (from, to) = (from as usize, to as usize);
+            self.data[from..to].fill(0xff);
+            for sector in from / SECTOR..to / SECTOR {
+                self.erases[sector] += 1;
+            }
+            Ok(())
+        }
+
+        fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+            let offset = offset as usize;
+            for (cell, byte) in self.data[offset..offset + bytes.len()]
+                .iter_mut()
+                .zip(bytes)
+            {
+                *cell &= byte;
+            }
+            Ok(())
+        }
+    }
+
+    fn store(flash: MockFlash) -> ConfigStore<MockFlash> {
+        ConfigStore::new(flash, 0, (SECTOR * SECTORS) as u32)
+    }
+
+    fn config(power: bool, center: u16) -> Config {
+        let mut config = Config::default();
+        config.power = power;
+        config.calibration.axes[0].center = center;
+        config
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    /// A valid config that encodes about as long as one can.
+    fn largest_config() -> Config {
+        let mut config = Config::default();
+        let points: [_; MAX_CURVE_POINTS] =
+            core::array::from_fn(|i| CurvePoint::new(AXIS_MIN + i as i16, AXIS_MIN));
+        config.curves = [Curve::Points(CurvePoints::new(&points)); AXIS_COUNT];
+        config.pixels.count = MAX_PIXELS as u8;
+        config.button_modes = [ButtonMode::Turbo(MAX_TURBO_HZ); BUTTON_COUNT];
+        config.axis_buttons[0].threshold = AXIS_MIN;
+        config.axis_buttons = [config.axis_buttons[0]; AXIS_BUTTON_COUNT];
+        let mut profile = config.profile(0).unwrap();
+        profile.name = ProfileName::new("sixteen letters!").unwrap();
+        config.profiles = [Some(profile); PROFILE_COUNT];
+        config
+    }
+
+    #[test]
+    fn largest_config_fits_a_record() {
+        let mut s = store(MockFlash::new());
+        assert_eq!(s.save(&largest_config()), Ok(()));
+    }
+
+    #[test]
+    fn large_records_take_several_slots_within_a_sector() {
+        let mut s = store(MockFlash::new());
+        s.save(&config(true, 1111)).unwrap();
+        // Too long for the slots left in the first sector.
+        s.save(&largest_config()).unwrap();
+        assert_eq!(s.flash.erases, [1, 1, 0, 0]);
+
+        let mut s = store(s.flash);
+        assert_eq!(s.load(), Some(largest_config()));
+        s.save(&config(false, 2222)).unwrap();
+        assert_eq!(s.flash.erases, [1, 1, 1, 0]);
+        assert_eq!(store(s.flash).load(), Some(config(false, 2222)));
+    }
+
+    #[test]
+    fn blank_flash_has_no_config() {
+        assert_eq!(store(MockFlash::new()).load(), None);
+    }
+
+    #[test]
+    fn saved_config_survives_reload() {
+        let mut s = store(MockFlash::new());
+        s.save(&config(false, 2000)).unwrap();
+
+        let mut reloaded = store(s.flash);
+        assert_eq!(reloaded.load(), Some(config(false, 2000)));
+    }
+
+    #[test]
+    fn newest_record_wins_after_wrapping() {
+        let mut s = store(MockFlash::new());
+        let total = s.slots * 3 + 5;
+        for i in 0..total {
+            s.save(&config(i % 2 == 0, 1000 + i as u16)).unwrap();
+        }
+
+        let last = total - 1;
+        let mut reloaded = store(s.flash);
+        assert_eq!(
+            reloaded.load(),
+            Some(config(last % 2 == 0, 1000 + last as u16))
+        );
+    }
+
+    #[test]
+    fn erases_are_spread_over_every_sector() {
+        let mut s = store(MockFlash::new());
+        for i in 0..s.slots * 4 {
+            s.save(&config(true, i as u16)).unwrap();
+        }
+        assert_eq!(s.flash.erases, [4; SECTORS]);
+    }
+
+    #[test]
+    fn corrupt_newest_record_falls_back_to_previous() {
+        let mut s = store(MockFlash::new());
+        s.save(&config(true, 1111)).unwrap();
+        s.save(&config(false, 2222)).unwrap();
+
+        let mut flash = s.flash;
+        flash.data[SLOT_SIZE + HEADER_SIZE] ^= 0x01;
+
+        assert_eq!(store(flash).load(), Some(config(true, 1111)));
+    }
+
+    #[test]
+    fn records_from_other_versions_are_ignored() {
+        let mut s = store(MockFlash::new());
+        s.save(&config(false, 1234)).unwrap();
+
+        let mut flash = s.flash;
+        flash.data[8..10].copy_from_slice(&(CONFIG_VERSION + 1).to_le_bytes());
+        // Keep the CRC valid, so that only the version is wrong.
+        let len = u16::from_le_bytes([flash.data[10], flash.data[11]]) as usize;
+        let crc = crc32(&flash.data[8..HEADER_SIZE + len]);
+        flash.data[4..8].copy_from_slice(&crc.to_le_bytes());
+
+        assert_eq!(store(flash).load(), None);
+    }
+
+    #[test]
+    fn version_follows_the_layout() {
+        // Fails when the size of an encoded config changes, as it does when
+        // an array or field is added. Bump CONFIG_VERSION, then update both
+        // numbers here.
+        let mut config = Config::default();
+        config.profiles = [config.profile(0); PROFILE_COUNT];
+        let mut buf = [0; MAX_RECORD_SIZE];
+        let len = postcard::to_slice(&config, &mut buf).unwrap().len();
+        assert_eq!((CONFIG_VERSION, len), (19, 1507));
+    }
+
+    #[test]
+    fn invalid_config_is_ignored() {
+        let mut s = store(MockFlash::new());
+        let mut bad = Config::default();
+        bad.calibration.axes[1].max = 9000;
+        s.save(&bad).unwrap();
+
+        assert_eq!(store(s.flash).load(), None);
+    }
+
+    #[test]
+    fn interrupted_write_moves_to_the_next_sector() {
+        let mut s = store(MockFlash::new());
+        s.save(&config(true, 1111)).unwrap();
+        // Half a header in the next slot, as if power was lost mid-write.
+        s.flash.data[SLOT_SIZE..SLOT_SIZE + 6].fill(0);
+
+        let mut s = store(s.flash);
+        assert_eq!(s.load(), Some(config(true, 1111)));
+        s.save(&config(false, 2222)).unwrap();
+
+        // The first sector, and the record in it, was left alone.
+        assert_eq!(s.flash.erases, [1, 1, 0, 0]);
+        assert_eq!(store(s.flash).load(), Some(config(false, 2222)));
+    }
+}
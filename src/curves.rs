@@ -0,0 +1,249 @@
+//! Response curves, reshaping calibrated axis values before they're reported.
+//!
+//! Every curve maps the axis range onto itself. Expo and the S-curve are
+//! symmetric about the center, while a list of points can take any shape.
+
+use serde::{Deserialize, Serialize};
+
+use crate::axis::{AXIS_MAX, AXIS_MIN};
+
+/// Most points a piecewise-linear curve can have.
+pub const MAX_CURVE_POINTS: usize = 16;
+
+/// Largest strength expo and the S-curve take, where the curve is all cubic.
+pub const MAX_STRENGTH: u8 = 100;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CurvePoint {
+    pub input: i16,
+    pub output: i16,
+}
+
+impl CurvePoint {
+    pub const fn new(input: i16, output: i16) -> Self {
+        Self { input, output }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CurvePoints {
+    /// Number of points in use, from 2 to [`MAX_CURVE_POINTS`].
+    pub count: u8,
+    /// In order of input. Values before the first point take its output, and
+    /// after the last point take that one's.
+    pub points: [CurvePoint; MAX_CURVE_POINTS],
+}
+
+impl CurvePoints {
+    /// A straight line through the given points, which must number from 2 to
+    /// [`MAX_CURVE_POINTS`].
+    pub fn new(points: &[CurvePoint]) -> Self {
+        let mut all = [points[points.len() - 1]; MAX_CURVE_POINTS];
+        all[..points.len()].copy_from_slice(points);
+        Self {
+            count: points.len() as u8,
+            points: all,
+        }
+    }
+
+    fn used(&self) -> &[CurvePoint] {
+        &self.points[..(self.count as usize).min(MAX_CURVE_POINTS)]
+    }
+
+    pub fn is_valid(&self) -> bool {
+        let range = AXIS_MIN..=AXIS_MAX;
+        (2..=MAX_CURVE_POINTS).contains(&(self.count as usize))
+            && self
+                .used()
+                .iter()
+                .all(|p| range.contains(&p.input) && range.contains(&p.output))
+            && self
+                .used()
+                .windows(2)
+                .all(|pair| pair[0].input < pair[1].input)
+    }
+
+    fn apply(&self, value: i16) -> i16 {
+        let points = self.used();
+        let (Some(first), Some(last)) = (points.first(), points.last()) else {
+            return value;
+        };
+        if value <= first.input {
+            return first.output;
+        }
+        if value >= last.input {
+            return last.output;
+        }
+        let i = points.partition_point(|p| p.input <= value);
+        let (from, to) = (points[i - 1], points[i]);
+        let t = value as i32 - from.input as i32;
+        let span = to.input as i32 - from.input as i32;
+        let rise = to.output as i32 - from.output as i32;
+        (from.output as i32 + rise * t / span) as i16
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Curve {
+    /// Values pass through unchanged.
+    #[default]
+    Linear,
+    /// Gentler near the center for finer control, steeper towards the ends.
+    /// Blends a straight line with a cubic, 0 being straight and
+    /// [`MAX_STRENGTH`] all cubic.
+    Expo(u8),
+    /// Gentle at the center and the ends, steep in between. Blends a
+    /// straight line with a smoothstep, with the same strengths as expo.
+    SCurve(u8),
+    /// Straight lines between points.
+    Points(CurvePoints),
+}
+
+impl Curve {
+    pub fn is_valid(&self) -> bool {
+        match self {
+            Curve::Linear => true,
+            Curve::Expo(strength) | Curve::SCurve(strength) => *strength <= MAX_STRENGTH,
+            Curve::Points(points) => points.is_valid(),
+        }
+    }
+
+    pub fn apply(&self, value: i16) -> i16 {
+        let value = value.max(AXIS_MIN);
+        match self {
+            Curve::Linear => value,
+            Curve::Expo(strength) => symmetric(value, *strength, |t| {
+                // t³, keeping t in 1/AXIS_MAX units.
+                t * t / MAX * t / MAX
+            }),
+            Curve::SCurve(strength) => symmetric(value, *strength, |t| {
+                // 3t² - 2t³
+                let t2 = t * t / MAX;
+                (3 * t2 - 2 * t2 * t / MAX).clamp(0, MAX)
+            }),
+            Curve::Points(points) => points.apply(value),
+        }
+    }
+}
+
+const MAX: i64 = AXIS_MAX as i64;
+
+/// Blends `value` with `shape` of its distance from the center, keeping its
+/// sign. `shape` maps 0..=MAX onto itself.
+fn symmetric(value: i16, strength: u8, shape: impl Fn(i64) -> i64) -> i16 {
+    let t = (value as i64).abs();
+    let strength = strength.min(MAX_STRENGTH) as i64;
+    let shaped = (t * (MAX_STRENGTH as i64 - strength) + shape(t) * strength) / MAX_STRENGTH as i64;
+    (shaped * (value as i64).signum()) as i16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monotonic(curve: Curve) -> bool {
+        let values: Vec<i16> = (AXIS_MIN..=AXIS_MAX)
+            .step_by(97)
+            .map(|v| curve.apply(v))
+            .collect();
+        values.windows(2).all(|pair| pair[0] <= pair[1])
+    }
+
+    #[test]
+    fn linear_passes_values_through() {
+        for value in [AXIS_MIN, -1000, 0, 1, AXIS_MAX] {
+            assert_eq!(Curve::Linear.apply(value), value);
+        }
+    }
+
+    #[test]
+    fn every_curve_keeps_the_ends_and_center() {
+        for curve in [
+            Curve::Expo(0),
+            Curve::Expo(60),
+            Curve::Expo(MAX_STRENGTH),
+            Curve::SCurve(40),
+            Curve::SCurve(MAX_STRENGTH),
+        ] {
+            assert_eq!(curve.apply(0), 0, "{curve:?}");
+            assert_eq!(curve.apply(AXIS_MAX), AXIS_MAX, "{curve:?}");
+            assert_eq!(curve.apply(AXIS_MIN), AXIS_MIN, "{curve:?}");
+            assert!(monotonic(curve), "{curve:?}");
+        }
+        // i16::MIN is outside the report's range, so it's treated as AXIS_MIN.
+        assert_eq!(Curve::Expo(50).apply(i16::MIN), AXIS_MIN);
+    }
+
+    #[test]
+    fn expo_softens_the_center() {
+        let half = AXIS_MAX / 2;
+        assert_eq!(Curve::Expo(0).apply(half), half);
+        // Half way between a line and a cube.
+        let t = half as f64 / AXIS_MAX as f64;
+        let expected = (t + t * t * t) / 2.0 * AXIS_MAX as f64;
+        let value = Curve::Expo(50).apply(half);
+        assert!((value as f64 - expected).abs() <= 1.0, "{value}");
+        assert_eq!(Curve::Expo(50).apply(-half), -value);
+    }
+
+    #[test]
+    fn s_curve_is_steepest_in_the_middle() {
+        let curve = Curve::SCurve(MAX_STRENGTH);
+        let quarter = AXIS_MAX / 4;
+        let near_center = curve.apply(quarter);
+        let middle = curve.apply(2 * quarter) - near_center;
+        let near_end = AXIS_MAX - curve.apply(3 * quarter);
+        assert!(near_center < middle && near_end < middle);
+        assert!(curve.apply(2 * quarter).abs_diff(2 * quarter) <= 2);
+    }
+
+    #[test]
+    fn points_interpolate_between_them() {
+        let curve = Curve::Points(CurvePoints::new(&[
+            CurvePoint::new(-20000, -30000),
+            CurvePoint::new(0, 0),
+            CurvePoint::new(10000, 1000),
+            CurvePoint::new(20000, 20000),
+        ]));
+        assert!(curve.is_valid());
+        assert_eq!(curve.apply(-32767), -30000);
+        assert_eq!(curve.apply(-10000), -15000);
+        assert_eq!(curve.apply(0), 0);
+        assert_eq!(curve.apply(5000), 500);
+        assert_eq!(curve.apply(15000), 10500);
+        assert_eq!(curve.apply(32767), 20000);
+    }
+
+    #[test]
+    fn points_can_make_a_deadband() {
+        let curve = Curve::Points(CurvePoints::new(&[
+            CurvePoint::new(AXIS_MIN, AXIS_MIN),
+            CurvePoint::new(-2000, 0),
+            CurvePoint::new(2000, 0),
+            CurvePoint::new(AXIS_MAX, AXIS_MAX),
+        ]));
+        assert_eq!(curve.apply(1500), 0);
+        assert!(curve.apply(AXIS_MAX - 1).abs_diff(AXIS_MAX - 1) <= 1);
+        assert_eq!(curve.apply(-2000), 0);
+        assert!(monotonic(curve));
+    }
+
+    #[test]
+    fn rejects_bad_curves() {
+        assert!(!Curve::Expo(MAX_STRENGTH + 1).is_valid());
+        assert!(!Curve::SCurve(255).is_valid());
+
+        let mut points = CurvePoints::new(&[CurvePoint::new(0, 0), CurvePoint::new(100, 100)]);
+        assert!(Curve::Points(points).is_valid());
+        points.count = 1;
+        assert!(!Curve::Points(points).is_valid());
+        points.count = MAX_CURVE_POINTS as u8 + 1;
+        assert!(!Curve::Points(points).is_valid());
+
+        // Inputs must strictly increase, and stay in the report's range.
+        let unordered = CurvePoints::new(&[CurvePoint::new(100, 0), CurvePoint::new(100, 100)]);
+        assert!(!Curve::Points(unordered).is_valid());
+        let out_of_range = CurvePoints::new(&[CurvePoint::new(i16::MIN, 0), CurvePoint::new(0, 0)]);
+        assert!(!Curve::Points(out_of_range).is_valid());
+    }
+}
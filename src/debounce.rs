@@ -0,0 +1,186 @@
+//! Debouncing for the buttons, which are sampled far faster than their
+//! contacts settle.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DebounceMode {
+    /// Counts up while the contact reads pressed and down while it reads
+    /// released, and only changes state at either end of the window. Rejects
+    /// noise in both directions, at the cost of a window of latency.
+    #[default]
+    Integrator,
+    /// Changes state on the first edge, then ignores the contact for the
+    /// window. No added latency, but a single glitch is seen as a press.
+    LockOut,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Button {
+    pressed: bool,
+    /// Integrator: milliseconds of pressed readings, up to the window.
+    level: u32,
+    /// Lock-out: time before which the contact is ignored.
+    locked_until: u64,
+}
+
+/// Debounces `N` buttons, each with its own window.
+pub struct Debouncer<const N: usize> {
+    buttons: [Button; N],
+    last_ms: Option<u64>,
+}
+
+impl<const N: usize> Debouncer<N> {
+    pub const fn new() -> Self {
+        Self {
+            buttons: [Button {
+                pressed: false,
+                level: 0,
+                locked_until: 0,
+            }; N],
+            last_ms: None,
+        }
+    }
+
+    /// Feeds in the contacts as read at `now_ms` and returns the debounced
+    /// state. The first call takes the contacts as they are.
+    pub fn update(
+        &mut self,
+        raw: [bool; N],
+        now_ms: u64,
+        mode: DebounceMode,
+        window_ms: &[u8; N],
+    ) -> [bool; N] {
+        let Some(last_ms) = self.last_ms.replace(now_ms) else {
+            for ((button, raw), &window) in self.buttons.iter_mut().zip(raw).zip(window_ms) {
+                button.pressed = raw;
+                button.level = if raw { window as u32 } else { 0 };
+            }
+            return raw;
+        };
+        let elapsed = now_ms.saturating_sub(last_ms).min(u32::MAX as u64) as u32;
+
+        for ((button, raw), &window) in self.buttons.iter_mut().zip(raw).zip(window_ms) {
+            let window = window as u32;
+            match mode {
+                DebounceMode::Integrator => {
+                    button.level = if raw {
+                        (button.level + elapsed).min(window)
+                    } else {
+                        button.level.saturating_sub(elapsed)
+                    };
+                    if window == 0 {
+                        button.pressed = raw;
+                    } else if button.level == window {
+                        button.pressed = true;
+                    } else if button.level == 0 {
+                        button.pressed = false;
+                    }
+                }
+                DebounceMode::LockOut => {
+                    if now_ms >= button.locked_until && raw != button.pressed {
+                        button.pressed = raw;
+                        button.locked_until = now_ms + window as u64;
+                    }
+                }
+            }
+        }
+        self.buttons.map(|button| button.pressed)
+    }
+}
+
+impl<const N: usize> Default for Debouncer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs a trace of one reading per millisecond, `1` for pressed, through
+    /// a single button and returns what the debouncer made of it.
+    fn run(mode: DebounceMode, window: u8, trace: &str) -> String {
+        let mut debouncer = Debouncer::<1>::new();
+        trace
+            .bytes()
+            .enumerate()
+            .map(|(ms, reading)| {
+                let [pressed] = debouncer.update([reading == b'1'], ms as u64, mode, &[window]);
+                if pressed {
+                    '1'
+                } else {
+                    '0'
+                }
+            })
+            .collect()
+    }
+
+    // Synthetic traces, one sample per millisecond, shaped like a typical
+    // microswitch: a press that chatters for 7 ms, and a release that bounces
+    // for 9 ms.
+    const PRESS: &str = "00000101011011111111111111111111";
+    const RELEASE: &str = "11111111000010100100000000000000";
+
+    #[test]
+    fn integrator_waits_out_the_chatter() {
+        assert_eq!(
+            run(DebounceMode::Integrator, 5, PRESS),
+            "00000000000000011111111111111111"
+        );
+        assert_eq!(
+            run(DebounceMode::Integrator, 5, RELEASE),
+            "11111111111111110000000000000000"
+        );
+    }
+
+    #[test]
+    fn lock_out_reacts_on_the_first_edge() {
+        assert_eq!(
+            run(DebounceMode::LockOut, 10, PRESS),
+            "00000111111111111111111111111111"
+        );
+        assert_eq!(
+            run(DebounceMode::LockOut, 10, RELEASE),
+            "11111111000000000000000000000000"
+        );
+    }
+
+    #[test]
+    fn lock_out_passes_a_glitch_through() {
+        assert_eq!(run(DebounceMode::LockOut, 3, "0001000000"), "0001110000");
+        assert_eq!(run(DebounceMode::Integrator, 3, "0001000000"), "0000000000");
+    }
+
+    #[test]
+    fn zero_window_passes_readings_straight_through() {
+        for mode in [DebounceMode::Integrator, DebounceMode::LockOut] {
+            assert_eq!(run(mode, 0, PRESS), PRESS);
+            assert_eq!(run(mode, 0, RELEASE), RELEASE);
+        }
+    }
+
+    #[test]
+    fn each_button_has_its_own_window() {
+        let mut debouncer = Debouncer::<2>::new();
+        let windows = [2, 6];
+        let mode = DebounceMode::Integrator;
+        debouncer.update([false, false], 0, mode, &windows);
+
+        let states: Vec<[bool; 2]> = (1..=6)
+            .map(|ms| debouncer.update([true, true], ms, mode, &windows))
+            .collect();
+        assert_eq!(states[1], [true, false]);
+        assert_eq!(states[5], [true, true]);
+    }
+
+    #[test]
+    fn slow_polls_count_the_time_between_them() {
+        let mut debouncer = Debouncer::<1>::new();
+        let mode = DebounceMode::Integrator;
+        debouncer.update([false], 0, mode, &[5]);
+        assert_eq!(debouncer.update([true], 4, mode, &[5]), [false]);
+        assert_eq!(debouncer.update([true], 8, mode, &[5]), [true]);
+    }
+}
@@ -0,0 +1,285 @@
+//! Rotary encoders, turned into relative axis motion or button pulses.
+//!
+//! The encoders themselves are counted elsewhere; this takes the steps each
+//! one has made since the last poll, clockwise positive.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{axis::ADC_MAX, inputs::BUTTON_COUNT};
+
+/// Encoders wired to the board.
+pub const ENCODER_COUNT: usize = 2;
+
+/// Relative axes in the report: the dial, then the wheel.
+pub const RELATIVE_COUNT: usize = 2;
+
+/// How far one step moves an encoder used as an absolute axis, out of the
+/// ADC's 4096, so 32 steps take it from the centre to either end.
+pub const ENCODER_AXIS_STEP: i32 = 64;
+
+/// Steps a pulse button will queue up, so that spinning a knob quickly
+/// doesn't keep its button going long after it stops.
+pub const MAX_PENDING_PULSES: u8 = 16;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EncoderMode {
+    #[default]
+    Off,
+    /// Moves the dial axis by one per step.
+    Dial,
+    /// Moves the wheel axis by one per step.
+    Wheel,
+    /// Pulses one of [`EncoderConfig::buttons`] per step.
+    Buttons,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EncoderConfig {
+    pub mode: EncoderMode,
+    /// Indices of the buttons pulsed for anticlockwise and clockwise steps.
+    pub buttons: [u8; 2],
+    /// How long each pulse is held for, and the gap after it, in
+    /// milliseconds.
+    pub pulse_ms: u8,
+}
+
+impl EncoderConfig {
+    pub const fn new() -> Self {
+        Self {
+            mode: EncoderMode::Off,
+            buttons: [30, 31],
+            pulse_ms: 20,
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.pulse_ms > 0 && self.buttons.iter().all(|&b| (b as usize) < BUTTON_COUNT)
+    }
+}
+
+/// Plays back steps on a button, one press per step with a gap between them
+/// so the host sees each one.
+#[derive(Clone, Copy, Debug, Default)]
+struct Pulses {
+    pending: u8,
+    pressed: bool,
+    until_ms: u64,
+}
+
+impl Pulses {
+    fn update(&mut self, now_ms: u64, width_ms: u8) -> bool {
+        if now_ms >= self.until_ms {
+            if self.pressed {
+                self.pressed = false;
+                self.until_ms = now_ms + width_ms as u64;
+            } else if self.pending > 0 {
+                self.pending -= 1;
+                self.pressed = true;
+                self.until_ms = now_ms + width_ms as u64;
+            }
+        }
+        self.pressed
+    }
+}
+
+pub struct Encoders {
+    /// Anticlockwise and clockwise pulses for each encoder.
+    pulses: [[Pulses; 2]; ENCODER_COUNT],
+    /// Motion not yet sent to the host, for the dial and the wheel.
+    motion: [i32; RELATIVE_COUNT],
+    /// Where each encoder has been turned to, as an ADC sample would read.
+    positions: [u16; ENCODER_COUNT],
+}
+
+impl Encoders {
+    pub const fn new() -> Self {
+        Self {
+            pulses: [[Pulses {
+                pending: 0,
+                pressed: false,
+                until_ms: 0,
+            }; 2]; ENCODER_COUNT],
+            motion: [0; RELATIVE_COUNT],
+            positions: [ADC_MAX.div_ceil(2); ENCODER_COUNT],
+        }
+    }
+
+    /// Where each encoder has been turned to, starting from the centre, for
+    /// encoders that are the source of an axis.
+    pub fn positions(&self) -> [u16; ENCODER_COUNT] {
+        self.positions
+    }
+
+    /// Feeds in the steps made since the last call, presses any pulse
+    /// buttons that are due, and returns the relative motion to report.
+    pub fn update(
+        &mut self,
+        steps: [i32; ENCODER_COUNT],
+        now_ms: u64,
+        config: &[EncoderConfig; ENCODER_COUNT],
+        buttons: &mut [bool; BUTTON_COUNT],
+    ) -> [i8; RELATIVE_COUNT] {
+        for (position, steps) in self.positions.iter_mut().zip(steps) {
+            let moved = *position as i32 + steps.saturating_mul(ENCODER_AXIS_STEP);
+            *position = moved.clamp(0, ADC_MAX as i32) as u16;
+        }
+
+        let mut in_use = [false; RELATIVE_COUNT];
+        for ((config, pulses), steps) in config.iter().zip(&mut self.pulses).zip(steps) {
+            match config.mode {
+                EncoderMode::Off => *pulses = Default::default(),
+                EncoderMode::Dial | EncoderMode::Wheel => {
+                    let axis = (config.mode == EncoderMode::Wheel) as usize;
+                    self.motion[axis] = self.motion[axis].saturating_add(steps);
+                    in_use[axis] = true;
+                    *pulses = Default::default();
+                }
+                EncoderMode::Buttons => {
+                    let direction = (steps > 0) as usize;
+                    let queued = &mut pulses[direction].pending;
+                    *queued = queued
+                        .saturating_add(steps.unsigned_abs().min(u8::MAX as u32) as u8)
+                        .min(MAX_PENDING_PULSES);
+                    for (pulse, &button) in pulses.iter_mut().zip(&config.buttons) {
+                        if pulse.update(now_ms, config.pulse_ms) {
+                            buttons[button as usize] = true;
+                        }
+                    }
+                }
+            }
+        }
+        // Motion left over from an encoder that has since been remapped would
+        // otherwise be stuck there.
+        for (motion, in_use) in self.motion.iter_mut().zip(in_use) {
+            if !in_use {
+                *motion = 0;
+            }
+        }
+        self.motion
+            .map(|motion| motion.clamp(-(i8::MAX as i32), i8::MAX as i32) as i8)
+    }
+
+    /// Takes motion the host has been sent out of what is still to send.
+    pub fn sent(&mut self, motion: [i8; RELATIVE_COUNT]) {
+        for (pending, sent) in self.motion.iter_mut().zip(motion) {
+            *pending -= sent as i32;
+        }
+    }
+}
+
+impl Default for Encoders {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(mode: EncoderMode) -> [EncoderConfig; ENCODER_COUNT] {
+        let mut config = [EncoderConfig::new(); ENCODER_COUNT];
+        config[0].mode = mode;
+        config
+    }
+
+    #[test]
+    fn steps_move_the_dial_until_sent() {
+        let mut encoders = Encoders::new();
+        let config = config(EncoderMode::Dial);
+        let mut buttons = [false; BUTTON_COUNT];
+        assert_eq!(encoders.update([3, 0], 0, &config, &mut buttons), [3, 0]);
+        // Not sent yet, so it carries over.
+        assert_eq!(encoders.update([-1, 0], 1, &config, &mut buttons), [2, 0]);
+        encoders.sent([2, 0]);
+        assert_eq!(encoders.update([0, 0], 2, &config, &mut buttons), [0, 0]);
+    }
+
+    #[test]
+    fn fast_spins_are_spread_over_several_reports() {
+        let mut encoders = Encoders::new();
+        let config = config(EncoderMode::Wheel);
+        let mut buttons = [false; BUTTON_COUNT];
+        assert_eq!(
+            encoders.update([-200, 0], 0, &config, &mut buttons),
+            [0, -127]
+        );
+        encoders.sent([0, -127]);
+        assert_eq!(encoders.update([0, 0], 1, &config, &mut buttons), [0, -73]);
+    }
+
+    #[test]
+    fn remapped_encoder_drops_its_motion() {
+        let mut encoders = Encoders::new();
+        let mut buttons = [false; BUTTON_COUNT];
+        encoders.update([5, 0], 0, &config(EncoderMode::Dial), &mut buttons);
+        let motion = encoders.update([0, 0], 1, &config(EncoderMode::Off), &mut buttons);
+        assert_eq!(motion, [0, 0]);
+    }
+
+    #[test]
+    fn positions_follow_every_encoder_and_stop_at_the_ends() {
+        let mut encoders = Encoders::new();
+        let config = config(EncoderMode::Off);
+        let mut buttons = [false; BUTTON_COUNT];
+        assert_eq!(encoders.positions(), [2048, 2048]);
+        encoders.update([2, -1], 0, &config, &mut buttons);
+        assert_eq!(encoders.positions(), [2176, 1984]);
+        encoders.update([100, -100], 1, &config, &mut buttons);
+        assert_eq!(encoders.positions(), [ADC_MAX, 0]);
+    }
+
+    /// Runs one poll per millisecond and records when `button` is pressed.
+    fn pulse_trace(encoders: &mut Encoders, steps: &[i32], button: usize) -> String {
+        let config = config(EncoderMode::Buttons);
+        steps
+            .iter()
+            .enumerate()
+            .map(|(ms, &step)| {
+                let mut buttons = [false; BUTTON_COUNT];
+                encoders.update([step, 0], ms as u64, &config, &mut buttons);
+                if buttons[button] {
+                    '1'
+                } else {
+                    '0'
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn each_step_is_one_pulse() {
+        let mut encoders = Encoders::new();
+        let mut steps = [0; 100];
+        steps[0] = 2;
+        let clockwise = "1".repeat(20) + &"0".repeat(20) + &"1".repeat(20) + &"0".repeat(40);
+        assert_eq!(pulse_trace(&mut encoders, &steps, 31), clockwise);
+
+        let mut encoders = Encoders::new();
+        steps[0] = -1;
+        let anticlockwise = "1".repeat(20) + &"0".repeat(80);
+        assert_eq!(pulse_trace(&mut encoders, &steps, 30), anticlockwise);
+    }
+
+    #[test]
+    fn pulses_queue_up_to_a_limit() {
+        let mut encoders = Encoders::new();
+        let mut steps = [0; 2000];
+        steps[0] = 100;
+        let trace = pulse_trace(&mut encoders, &steps, 31);
+        let pulses = trace.split('0').filter(|run| !run.is_empty()).count();
+        assert_eq!(pulses, MAX_PENDING_PULSES as usize);
+    }
+
+    #[test]
+    fn rejects_bad_settings() {
+        let mut config = EncoderConfig::new();
+        assert!(config.is_valid());
+        config.pulse_ms = 0;
+        assert!(!config.is_valid());
+
+        let mut config = EncoderConfig::new();
+        config.buttons[0] = BUTTON_COUNT as u8;
+        assert!(!config.is_valid());
+    }
+}
@@ -0,0 +1,265 @@
+//! Smoothing for the raw axis samples, before calibration.
+//!
+//! Each axis runs its samples through a median, then a moving average, then
+//! an exponential moving average, then hysteresis. Every stage can be turned
+//! off, and a chain with all of them off passes samples straight through.
+
+use serde::{Deserialize, Serialize};
+
+/// Longest window the median and the average can look back over.
+pub const MAX_WINDOW: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FilterConfig {
+    /// Samples the median is taken over, odd, 1 to turn it off. Throws out
+    /// single-sample spikes.
+    pub median: u8,
+    /// Samples averaged together, 1 to turn it off.
+    pub average: u8,
+    /// How much of the previous output the EMA keeps each poll, out of 256,
+    /// 0 to turn it off.
+    pub ema: u8,
+    /// How far the sample must move from the output before the output
+    /// follows, 0 to turn it off.
+    pub hysteresis: u8,
+}
+
+impl FilterConfig {
+    /// Every stage off.
+    pub const NONE: Self = Self {
+        median: 1,
+        average: 1,
+        ema: 0,
+        hysteresis: 0,
+    };
+
+    pub const fn new() -> Self {
+        Self {
+            median: 3,
+            average: 4,
+            ema: 0,
+            hysteresis: 2,
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        let window = 1..=MAX_WINDOW as u8;
+        window.contains(&self.median) && self.median % 2 == 1 && window.contains(&self.average)
+    }
+}
+
+impl Default for FilterConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The last [`MAX_WINDOW`] values a stage saw, newest last.
+#[derive(Clone, Copy, Debug)]
+struct History {
+    values: [u16; MAX_WINDOW],
+    /// How many have been seen, up to the window.
+    len: usize,
+}
+
+impl History {
+    const fn new() -> Self {
+        Self {
+            values: [0; MAX_WINDOW],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, value: u16) {
+        self.values.copy_within(1.., 0);
+        self.values[MAX_WINDOW - 1] = value;
+        self.len = (self.len + 1).min(MAX_WINDOW);
+    }
+
+    /// The newest `n` values, or all of them if fewer have been seen.
+    fn last(&self, n: u8) -> &[u16] {
+        let n = (n as usize).clamp(1, self.len.max(1));
+        &self.values[MAX_WINDOW - n..]
+    }
+}
+
+/// The state of one axis's filter chain.
+#[derive(Clone, Copy, Debug)]
+pub struct AxisFilter {
+    raw: History,
+    medians: History,
+    /// In 256ths, and rounded at each step, so even the slowest EMA gets all
+    /// the way to a steady input.
+    ema: Option<u32>,
+    output: Option<u16>,
+}
+
+impl AxisFilter {
+    pub const fn new() -> Self {
+        Self {
+            raw: History::new(),
+            medians: History::new(),
+            ema: None,
+            output: None,
+        }
+    }
+
+    pub fn update(&mut self, sample: u16, config: &FilterConfig) -> u16 {
+        self.raw.push(sample);
+        let value = median(self.raw.last(config.median));
+
+        self.medians.push(value);
+        let window = self.medians.last(config.average);
+        let value = (window.iter().map(|&v| v as u32).sum::<u32>() / window.len() as u32) as u16;
+
+        let scaled = (value as u32) << 8;
+        let ema = match self.ema {
+            Some(previous) => {
+                let keep = config.ema as u32;
+                (previous * keep + scaled * (256 - keep) + 128) / 256
+            }
+            None => scaled,
+        };
+        self.ema = Some(ema);
+        let value = ((ema + 128) >> 8) as u16;
+
+        let output = match self.output {
+            Some(output) if value.abs_diff(output) <= config.hysteresis as u16 => output,
+            _ => value,
+        };
+        self.output = Some(output);
+        output
+    }
+}
+
+impl Default for AxisFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The middle value, or the lower middle one of an even count.
+fn median(values: &[u16]) -> u16 {
+    let mut sorted = [0; MAX_WINDOW];
+    let sorted = &mut sorted[..values.len()];
+    sorted.copy_from_slice(values);
+    sorted.sort_unstable();
+    sorted[(sorted.len() - 1) / 2]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(config: FilterConfig, samples: &[u16]) -> Vec<u16> {
+        let mut filter = AxisFilter::new();
+        samples
+            .iter()
+            .map(|&sample| filter.update(sample, &config))
+            .collect()
+    }
+
+    #[test]
+    fn all_off_passes_samples_through() {
+        let samples = [0, 4095, 17, 18, 17, 2000];
+        assert_eq!(run(FilterConfig::NONE, &samples), samples);
+    }
+
+    #[test]
+    fn median_throws_out_a_spike() {
+        let config = FilterConfig {
+            median: 3,
+            ..FilterConfig::NONE
+        };
+        assert_eq!(
+            run(config, &[100, 100, 4000, 100, 100, 200, 200]),
+            [100, 100, 100, 100, 100, 100, 200]
+        );
+    }
+
+    #[test]
+    fn average_smooths_over_its_window() {
+        let config = FilterConfig {
+            average: 4,
+            ..FilterConfig::NONE
+        };
+        assert_eq!(
+            run(config, &[0, 400, 400, 400, 400, 400]),
+            [0, 200, 266, 300, 400, 400]
+        );
+    }
+
+    #[test]
+    fn ema_eases_towards_a_step() {
+        let config = FilterConfig {
+            ema: 128,
+            ..FilterConfig::NONE
+        };
+        let out = run(config, &[0, 1000, 1000, 1000, 1000, 1000, 1000]);
+        assert_eq!(&out[..4], &[0, 500, 750, 875]);
+        assert!(out.windows(2).all(|pair| pair[0] <= pair[1]));
+
+        // Even the heaviest EMA gets there in the end.
+        let config = FilterConfig {
+            ema: 255,
+            ..FilterConfig::NONE
+        };
+        let mut samples = vec![1000];
+        samples.extend([1001; 3000]);
+        assert_eq!(run(config, &samples).last(), Some(&1001));
+    }
+
+    #[test]
+    fn hysteresis_ignores_small_wobbles() {
+        let config = FilterConfig {
+            hysteresis: 2,
+            ..FilterConfig::NONE
+        };
+        assert_eq!(
+            run(config, &[1000, 1001, 999, 1002, 1003, 1001, 998]),
+            [1000, 1000, 1000, 1000, 1003, 1003, 998]
+        );
+    }
+
+    #[test]
+    fn default_chain_settles_a_noisy_input() {
+        // Jitter of a couple of steps with the odd spike, as the pots give.
+        let noise = [0i16, 2, -1, 1, -2, 900, 0, 1, -1, 2, -2, 0];
+        let samples: Vec<u16> = noise
+            .iter()
+            .cycle()
+            .take(60)
+            .map(|&n| (2048 + n) as u16)
+            .collect();
+        let out = run(FilterConfig::new(), &samples);
+        assert!(out[8..].iter().all(|&v| v == out[8]), "{out:?}");
+        assert!(out[8].abs_diff(2048) <= 2);
+    }
+
+    #[test]
+    fn windows_can_change_on_the_fly() {
+        let mut filter = AxisFilter::new();
+        let mut config = FilterConfig {
+            average: 2,
+            ..FilterConfig::NONE
+        };
+        for sample in [0, 0, 0, 800] {
+            filter.update(sample, &config);
+        }
+        config.average = 4;
+        assert_eq!(filter.update(800, &config), 400);
+    }
+
+    #[test]
+    fn rejects_bad_windows() {
+        assert!(FilterConfig::new().is_valid());
+        for (median, average) in [(0, 1), (2, 1), (9, 1), (1, 0), (1, 9)] {
+            let config = FilterConfig {
+                median,
+                average,
+                ..FilterConfig::NONE
+            };
+            assert!(!config.is_valid(), "{config:?}");
+        }
+    }
+}
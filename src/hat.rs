@@ -0,0 +1,305 @@
+//! The hat switch: four direction buttons or a pair of axes turned into one
+//! of eight directions, or neutral.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    axis::{AXIS_COUNT, AXIS_MAX},
+    inputs::BUTTON_COUNT,
+};
+
+/// Reported when no direction is held. It is outside the descriptor's
+/// logical range, which is how HID spells "null".
+pub const HAT_NEUTRAL: u8 = 8;
+
+/// How far an axis must be pushed before it counts as a direction.
+pub const HAT_AXIS_THRESHOLD: i16 = AXIS_MAX / 2;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HatMode {
+    #[default]
+    Off,
+    /// From the buttons in [`HatConfig::buttons`].
+    Buttons,
+    /// From the axes in [`HatConfig::axes`].
+    Axes,
+}
+
+/// What to do when opposite directions are held at once (simultaneous
+/// opposing cardinal directions, or SOCD).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Socd {
+    /// Opposites cancel out.
+    #[default]
+    Neutral,
+    /// The one pressed most recently wins.
+    LastWins,
+    /// Up beats down; left and right cancel out.
+    UpPriority,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HatConfig {
+    pub mode: HatMode,
+    /// Indices of the up, right, down and left buttons. They are taken out of
+    /// the button field while the hat uses them.
+    pub buttons: [u8; 4],
+    /// Indices of the horizontal and vertical axes.
+    pub axes: [u8; 2],
+    pub socd: Socd,
+}
+
+impl HatConfig {
+    pub const fn new() -> Self {
+        Self {
+            mode: HatMode::Off,
+            buttons: [0, 1, 2, 3],
+            axes: [0, 1],
+            socd: Socd::Neutral,
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.buttons.iter().all(|&b| (b as usize) < BUTTON_COUNT)
+            && self.axes.iter().all(|&a| (a as usize) < AXIS_COUNT)
+    }
+}
+
+const UP: usize = 0;
+const RIGHT: usize = 1;
+const DOWN: usize = 2;
+const LEFT: usize = 3;
+
+/// Turns direction buttons into hat values, remembering which were pressed
+/// most recently for [`Socd::LastWins`].
+pub struct Hat {
+    held: [bool; 4],
+    /// The most recently pressed direction of each opposing pair.
+    newest_vertical: usize,
+    newest_horizontal: usize,
+}
+
+impl Hat {
+    pub const fn new() -> Self {
+        Self {
+            held: [false; 4],
+            newest_vertical: UP,
+            newest_horizontal: RIGHT,
+        }
+    }
+
+    /// Takes the up, right, down and left buttons and returns the hat value.
+    pub fn from_buttons(&mut self, held: [bool; 4], socd: Socd) -> u8 {
+        for (dir, (&now, was)) in held.iter().zip(self.held).enumerate() {
+            if now && !was {
+                match dir {
+                    UP | DOWN => self.newest_vertical = dir,
+                    _ => self.newest_horizontal = dir,
+                }
+            }
+        }
+        self.held = held;
+
+        // Which of each pair wins when both are held, if either.
+        let (vertical, horizontal) = match socd {
+            Socd::Neutral => (None, None),
+            Socd::LastWins => (Some(self.newest_vertical), Some(self.newest_horizontal)),
+            Socd::UpPriority => (Some(UP), None),
+        };
+        let resolve = |positive: usize, negative: usize, winner: Option<usize>| {
+            let pair = (held[positive], held[negative]);
+            match pair {
+                (true, false) => 1,
+                (false, true) => -1,
+                (false, false) => 0,
+                (true, true) => match winner {
+                    Some(winner) if winner == positive => 1,
+                    Some(_) => -1,
+                    None => 0,
+                },
+            }
+        };
+        let dy = resolve(UP, DOWN, vertical);
+        let dx = resolve(RIGHT, LEFT, horizontal);
+        direction(dx, dy)
+    }
+
+    /// Works out the hat value from the buttons or axes `config` names, and
+    /// releases any buttons it used so they only show up on the hat.
+    pub fn map(
+        &mut self,
+        config: &HatConfig,
+        axes: &[i16; AXIS_COUNT],
+        buttons: &mut [bool; BUTTON_COUNT],
+    ) -> u8 {
+        match config.mode {
+            HatMode::Off => HAT_NEUTRAL,
+            HatMode::Buttons => {
+                let held = config.buttons.map(|b| buttons[b as usize]);
+                for b in config.buttons {
+                    buttons[b as usize] = false;
+                }
+                self.from_buttons(held, config.socd)
+            }
+            HatMode::Axes => {
+                let [x, y] = config.axes.map(|a| axes[a as usize]);
+                from_axes(x, y)
+            }
+        }
+    }
+}
+
+impl Default for Hat {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The hat value for an axis pair, split into eight equal sectors. The
+/// vertical axis runs downwards, as HID axes do.
+pub fn from_axes(x: i16, y: i16) -> u8 {
+    let (x, y) = (x as i32, -(y as i32));
+    if x.abs().max(y.abs()) < HAT_AXIS_THRESHOLD as i32 {
+        return HAT_NEUTRAL;
+    }
+    // A component counts when it's more than tan(22.5°) of the other.
+    let counts = |a: i32, b: i32| a.abs() * 1000 > b.abs() * 414;
+    let dx = if counts(x, y) { x.signum() } else { 0 };
+    let dy = if counts(y, x) { y.signum() } else { 0 };
+    direction(dx, dy)
+}
+
+/// 0 for up, counting clockwise in eighths of a turn.
+fn direction(dx: i32, dy: i32) -> u8 {
+    match (dx, dy) {
+        (0, 1) => 0,
+        (1, 1) => 1,
+        (1, 0) => 2,
+        (1, -1) => 3,
+        (0, -1) => 4,
+        (-1, -1) => 5,
+        (-1, 0) => 6,
+        (-1, 1) => 7,
+        _ => HAT_NEUTRAL,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const U: [bool; 4] = [true, false, false, false];
+    const R: [bool; 4] = [false, true, false, false];
+    const D: [bool; 4] = [false, false, true, false];
+    const L: [bool; 4] = [false, false, false, true];
+
+    fn or(a: [bool; 4], b: [bool; 4]) -> [bool; 4] {
+        core::array::from_fn(|i| a[i] || b[i])
+    }
+
+    #[test]
+    fn buttons_give_eight_directions() {
+        let mut hat = Hat::new();
+        let cases = [
+            ([false; 4], HAT_NEUTRAL),
+            (U, 0),
+            (or(U, R), 1),
+            (R, 2),
+            (or(D, R), 3),
+            (D, 4),
+            (or(D, L), 5),
+            (L, 6),
+            (or(U, L), 7),
+        ];
+        for (held, value) in cases {
+            assert_eq!(hat.from_buttons(held, Socd::Neutral), value);
+        }
+    }
+
+    #[test]
+    fn neutral_socd_cancels_opposites() {
+        let mut hat = Hat::new();
+        assert_eq!(hat.from_buttons(or(U, D), Socd::Neutral), HAT_NEUTRAL);
+        assert_eq!(hat.from_buttons(or(or(U, D), R), Socd::Neutral), 2);
+    }
+
+    #[test]
+    fn last_wins_socd_follows_the_newest_press() {
+        let mut hat = Hat::new();
+        hat.from_buttons(L, Socd::LastWins);
+        assert_eq!(hat.from_buttons(or(L, R), Socd::LastWins), 2);
+        // Releasing right gives left back.
+        assert_eq!(hat.from_buttons(L, Socd::LastWins), 6);
+
+        hat.from_buttons(D, Socd::LastWins);
+        assert_eq!(hat.from_buttons(or(D, U), Socd::LastWins), 0);
+        hat.from_buttons(U, Socd::LastWins);
+        assert_eq!(hat.from_buttons(or(D, U), Socd::LastWins), 4);
+    }
+
+    #[test]
+    fn up_priority_socd_prefers_up() {
+        let mut hat = Hat::new();
+        hat.from_buttons(U, Socd::UpPriority);
+        assert_eq!(hat.from_buttons(or(U, D), Socd::UpPriority), 0);
+        hat.from_buttons(D, Socd::UpPriority);
+        assert_eq!(hat.from_buttons(or(U, D), Socd::UpPriority), 0);
+        assert_eq!(hat.from_buttons(or(L, R), Socd::UpPriority), HAT_NEUTRAL);
+    }
+
+    #[test]
+    fn axes_split_into_sectors() {
+        let max = AXIS_MAX;
+        assert_eq!(from_axes(0, 0), HAT_NEUTRAL);
+        assert_eq!(from_axes(max / 4, -max / 4), HAT_NEUTRAL);
+        assert_eq!(from_axes(0, -max), 0);
+        assert_eq!(from_axes(max, -max), 1);
+        assert_eq!(from_axes(max, 0), 2);
+        assert_eq!(from_axes(max, max), 3);
+        assert_eq!(from_axes(0, max), 4);
+        assert_eq!(from_axes(-max, max), 5);
+        assert_eq!(from_axes(-max, 0), 6);
+        assert_eq!(from_axes(-max, -max), 7);
+        // Just inside the sector for right, 20° above the horizontal.
+        assert_eq!(from_axes(max, -(max / 100 * 36)), 2);
+        // And 25° above it, into up-right.
+        assert_eq!(from_axes(max, -(max / 100 * 47)), 1);
+    }
+
+    #[test]
+    fn buttons_on_the_hat_leave_the_button_field() {
+        let mut hat = Hat::new();
+        let config = HatConfig {
+            mode: HatMode::Buttons,
+            buttons: [4, 5, 6, 7],
+            ..HatConfig::new()
+        };
+        let mut buttons = [false; BUTTON_COUNT];
+        buttons[0] = true;
+        buttons[5] = true;
+        assert_eq!(hat.map(&config, &[0; AXIS_COUNT], &mut buttons), 2);
+        assert!(buttons[0]);
+        assert!(!buttons[5]);
+    }
+
+    #[test]
+    fn off_is_neutral_and_leaves_the_buttons() {
+        let mut hat = Hat::new();
+        let mut buttons = [true; BUTTON_COUNT];
+        let axes = [AXIS_MAX; AXIS_COUNT];
+        assert_eq!(hat.map(&HatConfig::new(), &axes, &mut buttons), HAT_NEUTRAL);
+        assert_eq!(buttons, [true; BUTTON_COUNT]);
+    }
+
+    #[test]
+    fn rejects_missing_inputs() {
+        let mut config = HatConfig::new();
+        assert!(config.is_valid());
+        config.buttons[2] = BUTTON_COUNT as u8;
+        assert!(!config.is_valid());
+
+        let mut config = HatConfig::new();
+        config.axes[1] = AXIS_COUNT as u8;
+        assert!(!config.is_valid());
+    }
+}
@@ -1,35 +1,309 @@
-use serde::ser::{Serialize, SerializeTuple, Serializer};
-use usbd_hid::descriptor::gen_hid_descriptor;
-use usbd_hid::descriptor::generator_prelude::*;
-
-#[gen_hid_descriptor(
-    (collection = APPLICATION, usage_page = GENERIC_DESKTOP, usage = GAMEPAD) = {
-      (collection = PHYSICAL, usage = POINTER) = {
-        (usage = X,) = {
-          #[item_settings data,variable] x=input;
+//! The joystick's HID report and its descriptor.
+//!
+//! The descriptor is built at startup from a [`ReportLayout`], leaving out
+//! the axes nothing drives. It is put together by hand, as
+//! `gen_hid_descriptor` can do neither that nor give the hat switch its
+//! logical range of 0 to 7.
+
+use heapless::Vec;
+
+use crate::{
+    axis::AXIS_COUNT,
+    encoders::{EncoderConfig, EncoderMode, ENCODER_COUNT, RELATIVE_COUNT},
+    sources::AxisSource,
+};
+
+/// Room for the descriptor with every axis in it.
+pub const DESCRIPTOR_CAPACITY: usize = 128;
+
+/// Length of the input report with every axis in it.
+pub const MAX_INPUT_LEN: usize = AXIS_COUNT * 2 + RELATIVE_COUNT + 1 + 4;
+
+/// X, Y, Z and Rz.
+const AXIS_USAGES: [u8; AXIS_COUNT] = [0x30, 0x31, 0x32, 0x35];
+/// Dial and Wheel.
+const RELATIVE_USAGES: [u8; RELATIVE_COUNT] = [0x37, 0x38];
+
+#[rustfmt::skip]
+const HEADER: &[u8] = &[
+    0x05, 0x01,             // Usage Page (Generic Desktop)
+    0x09, 0x05,             // Usage (Gamepad)
+    0xa1, 0x01,             // Collection (Application)
+];
+
+/// Everything after the axes, which is always there.
+#[rustfmt::skip]
+const HAT_BUTTONS_AND_VENDOR: &[u8] = &[
+    0x09, 0x39,             //   Usage (Hat Switch)
+    0x15, 0x00,             //   Logical Minimum (0)
+    0x25, 0x07,             //   Logical Maximum (7)
+    0x35, 0x00,             //   Physical Minimum (0)
+    0x46, 0x3b, 0x01,       //   Physical Maximum (315)
+    0x65, 0x14,             //   Unit (Degrees)
+    0x75, 0x04,             //   Report Size (4)
+    0x95, 0x01,             //   Report Count (1)
+    0x81, 0x42,             //   Input (Data, Variable, Absolute, Null State)
+    0x45, 0x00,             //   Physical Maximum (0)
+    0x65, 0x00,             //   Unit (None)
+    0x81, 0x03,             //   Input (Constant), padding to a byte
+    0x05, 0x09,             //   Usage Page (Button)
+    0x19, 0x01,             //   Usage Minimum (1)
+    0x29, 0x20,             //   Usage Maximum (32)
+    0x25, 0x01,             //   Logical Maximum (1)
+    0x75, 0x01,             //   Report Size (1)
+    0x95, 0x20,             //   Report Count (32)
+    0x81, 0x02,             //   Input (Data, Variable, Absolute)
+    0x06, 0x00, 0xff,       //   Usage Page (Vendor Defined)
+    0x09, 0x01,             //   Usage (1)
+    0x26, 0xff, 0x00,       //   Logical Maximum (255)
+    0x75, 0x08,             //   Report Size (8)
+    0x95, 0x03,             //   Report Count (3)
+    0xb1, 0x02,             //   Feature (Data, Variable, Absolute)
+    0x09, 0x02,             //   Usage (2)
+    0x95, 0x06,             //   Report Count (6)
+    0x91, 0x02,             //   Output (Data, Variable, Absolute)
+    0xc0,                   // End Collection
+];
+
+/// Which axes are in the report. Those left out are neither described nor
+/// sent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReportLayout {
+    /// X, Y, Z and Rz.
+    pub axes: [bool; AXIS_COUNT],
+    /// Dial and wheel.
+    pub relative: [bool; RELATIVE_COUNT],
+}
+
+impl ReportLayout {
+    pub const FULL: Self = Self {
+        axes: [true; AXIS_COUNT],
+        relative: [true; RELATIVE_COUNT],
+    };
+
+    /// The axes with a source, and the relative ones an encoder drives.
+    pub fn of(
+        axis_sources: &[AxisSource; AXIS_COUNT],
+        encoders: &[EncoderConfig; ENCODER_COUNT],
+    ) -> Self {
+        let drives = |mode| encoders.iter().any(|e| e.mode == mode);
+        Self {
+            axes: axis_sources.map(|source| source != AxisSource::None),
+            relative: [drives(EncoderMode::Dial), drives(EncoderMode::Wheel)],
+        }
+    }
+
+    pub fn descriptor(&self) -> Vec<u8, DESCRIPTOR_CAPACITY> {
+        let mut desc = Vec::new();
+        let mut push = |items: &[u8]| {
+            desc.extend_from_slice(items)
+                .expect("report descriptor is longer than DESCRIPTOR_CAPACITY")
         };
-        (usage = Y,) = {
-          #[item_settings data,variable] y=input;
+        push(HEADER);
+
+        let axes = present(&AXIS_USAGES, &self.axes);
+        if !axes.is_empty() {
+            push(&[0x09, 0x01, 0xa1, 0x00]); // Usage (Pointer), Collection (Physical)
+            for usage in &axes {
+                push(&[0x09, *usage]);
+            }
+            #[rustfmt::skip]
+            push(&[
+                0x16, 0x01, 0x80,   // Logical Minimum (-32767)
+                0x26, 0xff, 0x7f,   // Logical Maximum (32767)
+                0x75, 0x10,         // Report Size (16)
+                0x95, axes.len() as u8,
+                0x81, 0x02,         // Input (Data, Variable, Absolute)
+                0xc0,               // End Collection
+            ]);
+        }
+
+        let relative = present(&RELATIVE_USAGES, &self.relative);
+        if !relative.is_empty() {
+            for usage in &relative {
+                push(&[0x09, *usage]);
+            }
+            #[rustfmt::skip]
+            push(&[
+                0x15, 0x81,         // Logical Minimum (-127)
+                0x25, 0x7f,         // Logical Maximum (127)
+                0x75, 0x08,         // Report Size (8)
+                0x95, relative.len() as u8,
+                0x81, 0x06,         // Input (Data, Variable, Relative)
+            ]);
+        }
+
+        push(HAT_BUTTONS_AND_VENDOR);
+        desc
+    }
+
+    /// Writes the input report for `report` into `buf`, returning its
+    /// length, or `None` if it doesn't fit.
+    pub fn write_input(&self, report: &ControlPanelReport, buf: &mut [u8]) -> Option<usize> {
+        let mut bytes = Vec::<u8, MAX_INPUT_LEN>::new();
+        let axes = [report.x, report.y, report.x2, report.y2];
+        for value in present(&axes, &self.axes) {
+            bytes.extend_from_slice(&value.to_le_bytes()).ok()?;
+        }
+        for value in present(&[report.dial, report.wheel], &self.relative) {
+            bytes.push(value as u8).ok()?;
+        }
+        bytes.push(report.hat & 0x0f).ok()?;
+        bytes.extend_from_slice(&report.buttons).ok()?;
+
+        buf.get_mut(..bytes.len())?.copy_from_slice(&bytes);
+        Some(bytes.len())
+    }
+}
+
+/// The items whose flag is set.
+fn present<T: Copy, const N: usize>(items: &[T; N], flags: &[bool; N]) -> Vec<T, N> {
+    items
+        .iter()
+        .zip(flags)
+        .filter(|(_, &present)| present)
+        .map(|(&item, _)| item)
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ControlPanelReport {
+    pub x: i16,
+    pub y: i16,
+    pub x2: i16,
+    pub y2: i16,
+    /// Movement since the last report.
+    pub dial: i8,
+    pub wheel: i8,
+    /// 0 for up, counting clockwise in eighths of a turn, or
+    /// [`HAT_NEUTRAL`](crate::hat::HAT_NEUTRAL).
+    pub hat: u8,
+    /// One bit per button, button 1 in the lowest bit of the first byte.
+    pub buttons: [u8; 4],
+    /// Output only, see [`crate::host_reports`].
+    pub leds: [u8; 6],
+    /// Feature only, see [`crate::host_reports`].
+    pub settings: [u8; 3],
+}
+
+impl ControlPanelReport {
+    /// Whether the report carries relative motion, which has to be sent even
+    /// if the last report carried the same.
+    pub fn has_motion(&self) -> bool {
+        self.dial != 0 || self.wheel != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Walks the descriptor's short items, adding up the bits of each kind
+    /// of main item: input, output and feature.
+    fn report_bits(desc: &[u8]) -> [u32; 3] {
+        let (mut size, mut count, mut depth) = (0, 0, 0);
+        let mut bits = [0; 3];
+        let mut rest = desc;
+        while let [prefix, tail @ ..] = rest {
+            let len = match prefix & 0x03 {
+                3 => 4,
+                n => n as usize,
+            };
+            let data = tail[..len]
+                .iter()
+                .rev()
+                .fold(0u32, |v, &b| v << 8 | b as u32);
+            match prefix & 0xfc {
+                0x74 => size = data,
+                0x94 => count = data,
+                0x80 => bits[0] += size * count,
+                0x90 => bits[1] += size * count,
+                0xb0 => bits[2] += size * count,
+                0xa0 => depth += 1,
+                0xc0 => depth -= 1,
+                _ => {}
+            }
+            rest = &tail[len..];
+        }
+        assert_eq!(depth, 0, "unbalanced collections");
+        bits
+    }
+
+    /// Every combination of axes in or out of the report.
+    fn every_layout() -> impl Iterator<Item = ReportLayout> {
+        (0..1u32 << (AXIS_COUNT + RELATIVE_COUNT)).map(|bits| ReportLayout {
+            axes: core::array::from_fn(|i| bits & 1 << i != 0),
+            relative: core::array::from_fn(|i| bits & 1 << (AXIS_COUNT + i) != 0),
+        })
+    }
+
+    #[test]
+    fn descriptor_matches_report_sizes() {
+        let report = ControlPanelReport::default();
+        for layout in every_layout() {
+            let mut buf = [0u8; MAX_INPUT_LEN];
+            let len = layout.write_input(&report, &mut buf).unwrap();
+            let [input, output, feature] = report_bits(&layout.descriptor());
+            assert_eq!(input, len as u32 * 8, "{layout:?}");
+            assert_eq!(output, report.leds.len() as u32 * 8);
+            assert_eq!(feature, report.settings.len() as u32 * 8);
+        }
+    }
+
+    #[test]
+    fn fields_serialize_little_endian() {
+        let report = ControlPanelReport {
+            x: crate::axis::AXIS_MIN,
+            y: 0,
+            x2: 0x7ff0,
+            y2: 0x1234,
+            dial: -2,
+            wheel: 3,
+            hat: crate::hat::HAT_NEUTRAL,
+            buttons: [0x05, 0x00, 0x00, 0x80],
+            leds: [1; 6],
+            settings: [1, 2, 0],
         };
-      };
-      (collection = PHYSICAL, usage = POINTER) = {
-        (usage = Z,) = {
-          #[item_settings data,variable] x2=input;
+        let mut buf = [0u8; MAX_INPUT_LEN];
+        let len = ReportLayout::FULL.write_input(&report, &mut buf).unwrap();
+        assert_eq!(
+            &buf[..len],
+            &[
+                0x01, 0x80, 0x00, 0x00, 0xf0, 0x7f, 0x34, 0x12, 0xfe, 0x03, 0x08, 0x05, 0x00, 0x00,
+                0x80
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_axes_are_left_out() {
+        let layout = ReportLayout {
+            axes: [false, true, false, false],
+            relative: [false, true],
         };
-        (usage = 0x35,) = {
-          #[item_settings data,variable] y2=input;
+        let report = ControlPanelReport {
+            x: 1,
+            y: 0x0102,
+            dial: 4,
+            wheel: -1,
+            hat: 2,
+            buttons: [1, 2, 3, 4],
+            ..Default::default()
         };
-      };
-      (usage_page = BUTTON, usage_min = 1, usage_max = 2) = {
-        #[item_settings data,variable] s1=input;
-      };
+        let mut buf = [0u8; MAX_INPUT_LEN];
+        let len = layout.write_input(&report, &mut buf).unwrap();
+        assert_eq!(&buf[..len], &[0x02, 0x01, 0xff, 0x02, 1, 2, 3, 4]);
+
+        let desc = layout.descriptor();
+        assert!(desc.windows(2).any(|item| item == [0x09, 0x31]));
+        assert!(!desc.windows(2).any(|item| item == [0x09, 0x30]));
+        assert!(!desc.windows(2).any(|item| item == [0x09, 0x37]));
+    }
+
+    #[test]
+    fn short_buffer_is_refused() {
+        let mut buf = [0u8; MAX_INPUT_LEN - 1];
+        let report = ControlPanelReport::default();
+        assert_eq!(ReportLayout::FULL.write_input(&report, &mut buf), None);
     }
-)]
-pub struct ControlPanelReport {
-    pub x: i8,
-    pub y: i8,
-    pub x2: i8,
-    pub y2: i8,
-    pub s1: u8,
-    pub s2: u8,
 }
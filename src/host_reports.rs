@@ -0,0 +1,236 @@
+//! The output report the host sends to the device, and the feature report it
+//! can read and write. Both are declared in
+//! [`ControlPanelReport`](crate::hid_descriptor::ControlPanelReport).
+
+use crate::{
+    config::{Config, ConfigError},
+    hid_descriptor::ReportLayout,
+    led_patterns::Frame,
+};
+
+/// Number of status LEDs, one byte each in the output report.
+pub const LED_COUNT: usize = 6;
+
+/// Time a blinking LED spends on, and then off, in milliseconds.
+pub const BLINK_HALF_PERIOD_MS: u64 = 250;
+
+/// What the host wants an LED to do.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LedMode {
+    Off,
+    On,
+    Blink,
+    /// Leave the LED to the device's own animation.
+    #[default]
+    Auto,
+}
+
+impl LedMode {
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(LedMode::Off),
+            1 => Some(LedMode::On),
+            2 => Some(LedMode::Blink),
+            3 => Some(LedMode::Auto),
+            _ => None,
+        }
+    }
+}
+
+/// Parses the output report, which holds one [`LedMode`] byte per LED.
+pub fn parse_leds(data: &[u8]) -> Option<[LedMode; LED_COUNT]> {
+    let data: &[u8; LED_COUNT] = data.try_into().ok()?;
+    let mut modes = [LedMode::Auto; LED_COUNT];
+    for (mode, &byte) in modes.iter_mut().zip(data) {
+        *mode = LedMode::from_byte(byte)?;
+    }
+    Some(modes)
+}
+
+/// Combines the host's LED modes with the device's own animation into the
+/// frame to show right now.
+pub fn merge_leds(host: &[LedMode; LED_COUNT], animation: &Frame, now_ms: u64) -> Frame {
+    let blink_on = (now_ms / BLINK_HALF_PERIOD_MS) % 2 == 0;
+    core::array::from_fn(|i| match host[i] {
+        LedMode::Off => 0,
+        LedMode::On => 255,
+        LedMode::Blink if blink_on => 255,
+        LedMode::Blink => 0,
+        LedMode::Auto => animation[i],
+    })
+}
+
+pub const SETTINGS_REPORT_LEN: usize = 3;
+
+/// Settings the host can read and change through the feature report.
+///
+/// Byte 0 is the power flag (0 or 1), byte 1 the poll interval in
+/// milliseconds, and byte 2 the index of the active profile.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SettingsReport {
+    pub power: bool,
+    pub poll_ms: u8,
+    pub profile: u8,
+}
+
+impl SettingsReport {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            power: config.power,
+            poll_ms: config.poll_ms,
+            profile: config.active_profile,
+        }
+    }
+
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let [power, poll_ms, profile] = data.try_into().ok()?;
+        let power = match power {
+            0 => false,
+            1 => true,
+            _ => return None,
+        };
+        Some(Self {
+            power,
+            poll_ms,
+            profile,
+        })
+    }
+
+    pub fn write(&self, buf: &mut [u8]) -> Option<usize> {
+        let out = buf.get_mut(..SETTINGS_REPORT_LEN)?;
+        out.copy_from_slice(&[self.power as u8, self.poll_ms, self.profile]);
+        Some(SETTINGS_REPORT_LEN)
+    }
+
+    /// Returns `config` with these settings applied, switching profiles if
+    /// need be, if the result is valid. `layout` is the report's layout, see
+    /// [`Config::switch_profile`].
+    pub fn apply(&self, config: &Config, layout: &ReportLayout) -> Result<Config, ConfigError> {
+        let mut config = Config {
+            power: self.power,
+            poll_ms: self.poll_ms,
+            ..*config
+        };
+        if self.profile != config.active_profile {
+            config.switch_profile(self.profile as usize, layout)?;
+        }
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use LedMode::*;
+
+    #[test]
+    fn leds_parse_one_mode_per_byte() {
+        assert_eq!(
+            parse_leds(&[0, 1, 2, 3, 1, 0]),
+            Some([Off, On, Blink, Auto, On, Off])
+        );
+    }
+
+    #[test]
+    fn leds_reject_malformed_reports() {
+        assert_eq!(parse_leds(&[]), None);
+        assert_eq!(parse_leds(&[0; LED_COUNT + 1]), None);
+        assert_eq!(parse_leds(&[0, 0, 0, 4, 0, 0]), None);
+    }
+
+    #[test]
+    fn all_auto_falls_back_to_the_animation() {
+        let host = [Auto; LED_COUNT];
+        let animation = [255, 0, 40, 255, 0, 255];
+        assert_eq!(merge_leds(&host, &animation, 0), animation);
+    }
+
+    #[test]
+    fn host_modes_override_the_animation() {
+        let host = [Off, On, Auto, Auto, Off, On];
+        let animation = [255, 0, 255, 90, 255, 255];
+        assert_eq!(merge_leds(&host, &animation, 0), [0, 255, 255, 90, 0, 255]);
+    }
+
+    #[test]
+    fn blinking_leds_follow_the_clock() {
+        let host = [Blink, Off, Off, Off, Off, Blink];
+        let lit = [255, 0, 0, 0, 0, 255];
+        let dark = [0; LED_COUNT];
+        assert_eq!(merge_leds(&host, &dark, 0), lit);
+        assert_eq!(merge_leds(&host, &dark, BLINK_HALF_PERIOD_MS - 1), lit);
+        assert_eq!(merge_leds(&host, &dark, BLINK_HALF_PERIOD_MS), dark);
+        assert_eq!(merge_leds(&host, &dark, BLINK_HALF_PERIOD_MS * 2), lit);
+    }
+
+    #[test]
+    fn settings_round_trip() {
+        let settings = SettingsReport {
+            power: true,
+            poll_ms: 8,
+            profile: 2,
+        };
+        let mut buf = [0; 8];
+        let len = settings.write(&mut buf).unwrap();
+        assert_eq!(&buf[..len], &[1, 8, 2]);
+        assert_eq!(SettingsReport::parse(&buf[..len]), Some(settings));
+    }
+
+    #[test]
+    fn settings_reject_malformed_reports() {
+        assert_eq!(SettingsReport::parse(&[2, 8, 0]), None);
+        assert_eq!(SettingsReport::parse(&[1, 8]), None);
+
+        let settings = SettingsReport {
+            power: true,
+            poll_ms: 1,
+            profile: 0,
+        };
+        assert_eq!(settings.write(&mut [0; 2]), None);
+    }
+
+    #[test]
+    fn settings_apply_only_when_valid() {
+        let config = Config::default();
+        let layout = config.report_layout();
+        let applied = SettingsReport {
+            power: false,
+            poll_ms: 10,
+            profile: 0,
+        }
+        .apply(&config, &layout)
+        .unwrap();
+        assert!(!applied.power);
+        assert_eq!(applied.poll_ms, 10);
+        assert_eq!(applied.calibration, config.calibration);
+
+        let invalid = SettingsReport {
+            power: false,
+            poll_ms: 0,
+            profile: 0,
+        };
+        assert_eq!(invalid.apply(&config, &layout), Err(ConfigError::PollRate));
+    }
+
+    #[test]
+    fn settings_switch_profiles() {
+        let mut config = Config::default();
+        let layout = config.report_layout();
+        config.switch_profile(1, &layout).unwrap();
+        config.led_pattern = crate::led_patterns::Pattern::Chase;
+
+        let mut settings = SettingsReport::from_config(&config);
+        settings.profile = 0;
+        let applied = settings.apply(&config, &layout).unwrap();
+        assert_eq!(applied.active_profile, 0);
+        assert_eq!(applied.led_pattern, Config::default().led_pattern);
+
+        settings.profile = 4;
+        assert_eq!(
+            settings.apply(&config, &layout),
+            Err(ConfigError::ActiveProfile)
+        );
+    }
+}
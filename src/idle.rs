@@ -0,0 +1,122 @@
+//! HID idle rate handling.
+//!
+//! The host uses SET_IDLE to say how often it wants an unchanged input report
+//! repeated. Between repeats, reports only go out when the inputs change.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IdleRate {
+    /// The host has not set an idle rate, so report on every poll.
+    Unset,
+    /// Only report when the inputs change.
+    Indefinite,
+    /// Report when the inputs change, and repeat after this many
+    /// milliseconds without a change.
+    Millis(u32),
+}
+
+impl IdleRate {
+    /// Converts from embassy-usb's representation, in which `u32::MAX`
+    /// stands for an indefinite rate.
+    pub fn from_ms(ms: u32) -> Self {
+        match ms {
+            u32::MAX => IdleRate::Indefinite,
+            ms => IdleRate::Millis(ms),
+        }
+    }
+
+    /// Converts back to embassy-usb's representation. `None` rejects the
+    /// GET_IDLE request, as there is no rate to report.
+    pub fn as_ms(&self) -> Option<u32> {
+        match self {
+            IdleRate::Unset => None,
+            IdleRate::Indefinite => Some(u32::MAX),
+            IdleRate::Millis(ms) => Some(*ms),
+        }
+    }
+}
+
+/// Remembers the last report sent, to decide whether the next one is due.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ReportGate<R> {
+    last: Option<(R, u64)>,
+}
+
+impl<R: Copy + PartialEq> ReportGate<R> {
+    pub const fn new() -> Self {
+        Self { last: None }
+    }
+
+    pub fn is_due(&self, report: &R, idle: IdleRate, now_ms: u64) -> bool {
+        let Some((last, sent_ms)) = &self.last else {
+            return true;
+        };
+        match idle {
+            IdleRate::Unset => true,
+            IdleRate::Indefinite => report != last,
+            IdleRate::Millis(ms) => report != last || now_ms - sent_ms >= ms as u64,
+        }
+    }
+
+    pub fn mark_sent(&mut self, report: R, now_ms: u64) {
+        self.last = Some((report, now_ms));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sent(report: u8, at: u64) -> ReportGate<u8> {
+        let mut gate = ReportGate::new();
+        gate.mark_sent(report, at);
+        gate
+    }
+
+    #[test]
+    fn first_report_is_always_due() {
+        let gate = ReportGate::new();
+        assert!(gate.is_due(&1, IdleRate::Indefinite, 0));
+        assert!(gate.is_due(&1, IdleRate::Millis(500), 0));
+    }
+
+    #[test]
+    fn unset_reports_every_poll() {
+        assert!(sent(1, 100).is_due(&1, IdleRate::Unset, 101));
+    }
+
+    #[test]
+    fn indefinite_reports_only_changes() {
+        let gate = sent(1, 100);
+        assert!(!gate.is_due(&1, IdleRate::Indefinite, 101));
+        assert!(!gate.is_due(&1, IdleRate::Indefinite, 100_000));
+        assert!(gate.is_due(&2, IdleRate::Indefinite, 101));
+    }
+
+    #[test]
+    fn timed_rate_repeats_after_the_idle_period() {
+        let gate = sent(1, 100);
+        let idle = IdleRate::Millis(40);
+        assert!(!gate.is_due(&1, idle, 101));
+        assert!(!gate.is_due(&1, idle, 139));
+        assert!(gate.is_due(&1, idle, 140));
+        assert!(gate.is_due(&2, idle, 101));
+    }
+
+    #[test]
+    fn idle_period_restarts_on_every_send() {
+        let mut gate = sent(1, 100);
+        let idle = IdleRate::Millis(40);
+        gate.mark_sent(2, 120);
+        assert!(!gate.is_due(&2, idle, 140));
+        assert!(gate.is_due(&2, idle, 160));
+    }
+
+    #[test]
+    fn embassy_representation_round_trips() {
+        assert_eq!(IdleRate::from_ms(u32::MAX), IdleRate::Indefinite);
+        assert_eq!(IdleRate::from_ms(8), IdleRate::Millis(8));
+        assert_eq!(IdleRate::Indefinite.as_ms(), Some(u32::MAX));
+        assert_eq!(IdleRate::Millis(8).as_ms(), Some(8));
+        assert_eq!(IdleRate::Unset.as_ms(), None);
+    }
+}
@@ -0,0 +1,86 @@
+//! Snapshot of everything the joystick read in one pass of its loop.
+
+use serde::Serialize;
+
+use crate::{
+    axis::AXIS_COUNT, encoders::RELATIVE_COUNT, hat::HAT_NEUTRAL,
+    hid_descriptor::ControlPanelReport,
+};
+
+/// Buttons in the report. Not all of them need to be wired up.
+pub const BUTTON_COUNT: usize = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct Inputs {
+    /// ADC samples before calibration, in report axis order.
+    pub raw: [u16; AXIS_COUNT],
+    /// Axis values as sent to the host.
+    pub axes: [i16; AXIS_COUNT],
+    /// Dial and wheel motion not yet sent to the host.
+    pub relative: [i8; RELATIVE_COUNT],
+    /// Buttons as sent to the host, without any that went to the hat.
+    pub buttons: [bool; BUTTON_COUNT],
+    pub hat: u8,
+}
+
+impl Default for Inputs {
+    fn default() -> Self {
+        Self {
+            raw: [0; AXIS_COUNT],
+            axes: [0; AXIS_COUNT],
+            relative: [0; RELATIVE_COUNT],
+            buttons: [false; BUTTON_COUNT],
+            hat: HAT_NEUTRAL,
+        }
+    }
+}
+
+impl Inputs {
+    pub fn report(&self) -> ControlPanelReport {
+        let [x, y, x2, y2] = self.axes;
+        let [dial, wheel] = self.relative;
+        let mask = self
+            .buttons
+            .iter()
+            .rev()
+            .fold(0u32, |mask, &pressed| mask << 1 | pressed as u32);
+        ControlPanelReport {
+            x,
+            y,
+            x2,
+            y2,
+            dial,
+            wheel,
+            hat: self.hat,
+            buttons: mask.to_le_bytes(),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_carries_every_input() {
+        let mut inputs = Inputs {
+            raw: [1, 2, 3, 4],
+            axes: [-100, 200, -300, 400],
+            relative: [-5, 6],
+            buttons: [false; BUTTON_COUNT],
+            hat: 3,
+        };
+        inputs.buttons[0] = true;
+        inputs.buttons[9] = true;
+        inputs.buttons[31] = true;
+        let report = inputs.report();
+        assert_eq!(
+            (report.x, report.y, report.x2, report.y2),
+            (-100, 200, -300, 400)
+        );
+        assert_eq!((report.dial, report.wheel), (-5, 6));
+        assert_eq!(report.hat, 3);
+        assert_eq!(report.buttons, [0b0000_0001, 0b0000_0010, 0, 0b1000_0000]);
+    }
+}
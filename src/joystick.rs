@@ -1,10 +1,7 @@
 use defmt::warn;
-use embassy_rp::{
-    adc::{Adc, AdcPin, Async, Channel},
-    gpio::{Input, Level, Output, Pin, Pull},
-};
+use embassy_rp::adc::{Adc, Async, Channel};
 use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, mutex::Mutex};
-use embassy_time::Timer;
+use embassy_time::{Instant, Timer};
 use embassy_usb::{
     class::hid::{self, HidReader, HidReaderWriter},
     control::OutResponse,
@@ -14,31 +11,96 @@ use embassy_usb::{
     class::hid::{HidWriter, ReportId, RequestHandler},
     driver::Driver,
 };
+use heapless::Vec;
 use static_cell::StaticCell;
-use usbd_hid::descriptor::SerializedDescriptor;
+use usb_joystick::{
+    axis::AXIS_COUNT,
+    button_modes::ButtonModes,
+    debounce::Debouncer,
+    encoders::Encoders,
+    filters::AxisFilter,
+    hat::Hat,
+    hid_descriptor::{ReportLayout, DESCRIPTOR_CAPACITY, MAX_INPUT_LEN},
+    host_reports::{self, SettingsReport},
+    idle::{IdleRate, ReportGate},
+    inputs::{Inputs, BUTTON_COUNT},
+    profiles::{ComboWatch, PROFILE_COUNT},
+    remap::Remapper,
+    sources::{AxisSource, Samples, ADC_CHANNELS},
+    virtual_controls::{AxisButtons, ButtonAxes, BUTTON_AXIS_COUNT},
+};
 
-use crate::{hid_descriptor::ControlPanelReport, state::SharedState};
+use crate::{
+    buttons::Buttons,
+    leds::{self, LedCommand},
+    rotary, sensor_bus,
+    state::{SharedState, INPUT_UPDATES},
+    storage,
+};
 
-pub struct MyRequestHandler {}
+pub struct MyRequestHandler {
+    layout: ReportLayout,
+    state: &'static Mutex<CriticalSectionRawMutex, SharedState>,
+}
 
 impl RequestHandler for MyRequestHandler {
-    fn get_report(&mut self, id: ReportId, _buf: &mut [u8]) -> Option<usize> {
+    fn get_report(&mut self, id: ReportId, buf: &mut [u8]) -> Option<usize> {
         defmt::info!("Get report for {:?}", id);
-        None
+        // Nothing holds the lock across an await, so this only fails if the
+        // handler is somehow re-entered.
+        let state = self.state.try_lock().ok()?;
+        match id {
+            ReportId::In(_) => self.layout.write_input(&state.inputs.report(), buf),
+            ReportId::Feature(_) => SettingsReport::from_config(&state.config).write(buf),
+            ReportId::Out(_) => None,
+        }
     }
 
     fn set_report(&mut self, id: ReportId, data: &[u8]) -> OutResponse {
         defmt::info!("Set report for {:?}: {=[u8]}", id, data);
-        OutResponse::Accepted
+        let Ok(mut state) = self.state.try_lock() else {
+            return OutResponse::Rejected;
+        };
+        match id {
+            ReportId::Out(_) => match host_reports::parse_leds(data) {
+                Some(modes) => {
+                    leds::send(LedCommand::Host(modes));
+                    OutResponse::Accepted
+                }
+                None => OutResponse::Rejected,
+            },
+            ReportId::Feature(_) => {
+                let Some(settings) = SettingsReport::parse(data) else {
+                    return OutResponse::Rejected;
+                };
+                match settings.apply(&state.config, &state.layout) {
+                    Ok(config) => {
+                        leds::config_changed(&state.config, &config);
+                        state.config = config;
+                        storage::request_save();
+                        OutResponse::Accepted
+                    }
+                    Err(e) => {
+                        warn!("Rejected settings report: {}", e.message());
+                        OutResponse::Rejected
+                    }
+                }
+            }
+            ReportId::In(_) => OutResponse::Rejected,
+        }
     }
 
     fn set_idle_ms(&mut self, id: Option<ReportId>, dur: u32) {
         defmt::info!("Set idle rate for {:?} to {:?}", id, dur);
+        // There is only one input report, so the rate applies whatever the id.
+        if let Ok(mut state) = self.state.try_lock() {
+            state.idle = IdleRate::from_ms(dur);
+        }
     }
 
     fn get_idle_ms(&mut self, id: Option<ReportId>) -> Option<u32> {
         defmt::info!("Get idle rate for {:?}", id);
-        None
+        self.state.try_lock().ok()?.idle.as_ms()
     }
 }
 
@@ -46,11 +108,12 @@ pub struct HidResponderRunner<'a, D>
 where
     D: Driver<'a>,
 {
-    reader: HidReader<'a, D, 1>,
+    reader: HidReader<'a, D, 8>,
+    handler: MyRequestHandler,
 }
 impl<'a, D: Driver<'a>> HidResponderRunner<'a, D> {
-    pub async fn run(self) -> ! {
-        self.reader.run(true, &mut MyRequestHandler {}).await;
+    pub async fn run(mut self) -> ! {
+        self.reader.run(false, &mut self.handler).await;
     }
 }
 
@@ -59,148 +122,193 @@ where
     D: Driver<'static>,
 {
     adc: Adc<'static, Async>,
-    vx_analog: Channel<'static>,
-    vy_analog: Channel<'static>,
-    vz_analog: Channel<'static>,
-    s1: Input<'static>,
-    s2: Input<'static>,
-    led_0: Output<'static>,
-    led_1: Output<'static>,
-    led_2: Output<'static>,
-    led_3: Output<'static>,
-    led_4: Output<'static>,
-    led_5: Output<'static>,
-    writer: HidWriter<'static, D, 8>,
+    adc_channels: [Channel<'static>; ADC_CHANNELS],
+    temperature: Channel<'static>,
+    buttons: Buttons,
+    debouncer: Debouncer<BUTTON_COUNT>,
+    encoders: Encoders,
+    filters: [AxisFilter; AXIS_COUNT],
+    combo: ComboWatch,
+    remapper: Remapper,
+    modes: ButtonModes,
+    axis_buttons: AxisButtons,
+    button_axes: ButtonAxes,
+    hat: Hat,
+    writer: HidWriter<'static, D, 16>,
+    layout: ReportLayout,
     state: &'static Mutex<CriticalSectionRawMutex, SharedState>,
 }
 impl<D: Driver<'static>> JoystickRunner<D> {
     pub async fn run(&mut self) -> ! {
-        let mut counter: u16 = 0;
+        let mut poll_ms = 1;
+        let mut gate = ReportGate::new();
 
         loop {
-            _ = Timer::after_millis(1).await;
-            let report = ControlPanelReport {
-                x: -((self.adc.read(&mut self.vx_analog).await.unwrap_or_default() / 16) as i16
-                    - 128) as i8,
-                y: ((self.adc.read(&mut self.vy_analog).await.unwrap_or_default() / 16) as i16
-                    - 128) as i8,
-                x2: -((self.adc.read(&mut self.vz_analog).await.unwrap_or_default() / 16) as i16
-                    - 128) as i8,
-                y2: 0,
-                s1: if self.s1.is_low() { 0 } else { 255 },
-                s2: if self.s2.is_low() { 0 } else { 255 },
+            _ = Timer::after_millis(poll_ms as u64).await;
+            let mut samples = Samples {
+                sensor: sensor_bus::latest(),
+                ..Default::default()
             };
-            // Send the report.
-            match self.writer.write_serialize(&report).await {
-                Ok(()) => {}
-                Err(e) => warn!("Failed to send report: {:?}", e),
+            for (sample, channel) in samples.adc.iter_mut().zip(&mut self.adc_channels) {
+                *sample = self.adc.read(channel).await.unwrap_or_default();
             }
+            samples.temperature = self
+                .adc
+                .read(&mut self.temperature)
+                .await
+                .unwrap_or_default();
+            let contacts = self.buttons.read().await;
+            let steps = rotary::take_steps();
+            let now = Instant::now().as_millis();
+            let (inputs, idle) = {
+                let mut guard = self.state.lock().await;
+                let state = &mut *guard;
+                let physical = self.debouncer.update(
+                    contacts,
+                    now,
+                    state.config.debounce,
+                    &state.config.debounce_ms,
+                );
+                if self.combo.update(&state.config.profile_combo, &physical) {
+                    let previous = state.config;
+                    let next = (previous.active_profile as usize + 1) % PROFILE_COUNT;
+                    match state.config.switch_profile(next, &self.layout) {
+                        Ok(()) => {
+                            leds::config_changed(&previous, &state.config);
+                            storage::request_save();
+                        }
+                        Err(e) => warn!("Can't switch profiles: {}", e.message()),
+                    }
+                }
+                let config = &state.config;
+                let mapped = self.remapper.update(&config.button_map, &physical);
+                let mut buttons = self.modes.update(&config.button_modes, &mapped, now);
+                let relative = self
+                    .encoders
+                    .update(steps, now, &config.encoders, &mut buttons);
+                samples.encoders = self.encoders.positions();
+                let mut driven = [false; BUTTON_AXIS_COUNT];
+                for source in config.axis_sources {
+                    if let AxisSource::Buttons(axis) = source {
+                        driven[axis as usize] = true;
+                    }
+                }
+                self.button_axes
+                    .update(&config.button_axes, &driven, now, &mut buttons);
+                samples.button_axes = self.button_axes.positions();
 
-            // Update the LEDs.
-            if (*self.state.lock().await).power {
-                counter = counter.wrapping_add(1);
-            } else {
-                counter = 0;
-            }
-            if (counter & 0b00000100) != 0 {
-                self.led_0.set_high();
-            } else {
-                self.led_0.set_low();
-            }
-            if (counter & 0b00001000) != 0 {
-                self.led_1.set_high();
-            } else {
-                self.led_1.set_low();
-            }
-            if (counter & 0b00010000) != 0 {
-                self.led_2.set_high();
-            } else {
-                self.led_2.set_low();
-            }
-            if (counter & 0b00100000) != 0 {
-                self.led_3.set_high();
-            } else {
-                self.led_3.set_low();
-            }
-            if (counter & 0b01000000) != 0 {
-                self.led_4.set_high();
-            } else {
-                self.led_4.set_low();
-            }
-            if (counter & 0b10000000) != 0 {
-                self.led_5.set_high();
-            } else {
-                self.led_5.set_low();
+                let mut sampled = config.axis_sources.map(|source| source.sample(&samples));
+                for ((sample, filter), filter_config) in sampled
+                    .iter_mut()
+                    .zip(&mut self.filters)
+                    .zip(&config.filters)
+                {
+                    *sample = sample.map(|raw| filter.update(raw, filter_config));
+                }
+                let raw = sampled.map(Option::unwrap_or_default);
+                if let Some(capture) = &mut state.capture {
+                    capture.update(&raw);
+                }
+                // Axes with nothing to read are left centred.
+                let mut axes = [0; AXIS_COUNT];
+                for (i, (axis, sample)) in axes.iter_mut().zip(sampled).enumerate() {
+                    if let Some(raw) = sample {
+                        *axis = config.map_axis(i, raw);
+                    }
+                }
+                self.axis_buttons
+                    .update(&config.axis_buttons, &axes, &mut buttons);
+                let hat = self.hat.map(&config.hat, &axes, &mut buttons);
+                let inputs = Inputs {
+                    raw,
+                    axes,
+                    relative,
+                    buttons,
+                    hat,
+                };
+                poll_ms = config.poll_ms;
+                state.inputs = inputs;
+                (inputs, state.idle)
+            };
+            INPUT_UPDATES.sender().send(inputs);
+            let report = inputs.report();
+            // Send the report, if the inputs changed or the idle period is up.
+            // Motion always goes out, as it's only counted once it's sent.
+            if report.has_motion() || gate.is_due(&report, idle, now) {
+                let mut buf = [0; MAX_INPUT_LEN];
+                let len = self
+                    .layout
+                    .write_input(&report, &mut buf)
+                    .unwrap_or_default();
+                match self.writer.write(&buf[..len]).await {
+                    Ok(()) => {
+                        gate.mark_sent(report, now);
+                        self.encoders.sent(inputs.relative);
+                    }
+                    Err(e) => warn!("Failed to send report: {:?}", e),
+                }
             }
         }
     }
 }
 
+/// `adc_channels` are the ADC inputs on GPIO 26 to 29, in order, and
+/// `layout` the axes the report should have, which can't change once the
+/// device has enumerated.
 pub(crate) fn make_joystick<D>(
     builder: &mut Builder<'static, D>,
     adc: Adc<'static, Async>,
-    pin_vx: impl AdcPin,
-    pin_vy: impl AdcPin,
-    pin_vz: impl AdcPin,
-    pin_s1: impl Pin,
-    pin_s2: impl Pin,
-    pin_led0: impl Pin,
-    pin_led1: impl Pin,
-    pin_led2: impl Pin,
-    pin_led3: impl Pin,
-    pin_led4: impl Pin,
-    pin_led5: impl Pin,
+    adc_channels: [Channel<'static>; ADC_CHANNELS],
+    temperature: Channel<'static>,
+    buttons: Buttons,
+    layout: ReportLayout,
     state: &'static Mutex<CriticalSectionRawMutex, SharedState>,
 ) -> (JoystickRunner<D>, HidResponderRunner<'static, D>)
 where
     D: Driver<'static>,
 {
+    static DESCRIPTOR: StaticCell<Vec<u8, DESCRIPTOR_CAPACITY>> = StaticCell::new();
+    // Control requests (GET_REPORT / SET_REPORT on endpoint 0) go here, while
+    // output reports on the interrupt endpoint go to the responder's handler.
+    static HANDLER: StaticCell<MyRequestHandler> = StaticCell::new();
     let config = hid::Config {
-        report_descriptor: ControlPanelReport::desc(),
-        request_handler: None,
-        poll_ms: 60,
+        report_descriptor: DESCRIPTOR.init(layout.descriptor()),
+        request_handler: Some(HANDLER.init(MyRequestHandler { layout, state })),
+        poll_ms: 1,
         max_packet_size: 64,
     };
     let hid = {
         static STATE: StaticCell<hid::State> = StaticCell::new();
         let state = STATE.init(hid::State::new());
-        HidReaderWriter::<_, 1, 8>::new(builder, state, config)
+        HidReaderWriter::<_, 8, 16>::new(builder, state, config)
     };
 
     // Joystick setup
     let (reader, writer) = hid.split();
 
-    let vx_analog = Channel::new_pin(pin_vx, Pull::None);
-    let vy_analog = Channel::new_pin(pin_vy, Pull::None);
-    let vz_analog = Channel::new_pin(pin_vz, Pull::None);
-    let s1 = Input::new(pin_s1, Pull::Up);
-    let s2 = Input::new(pin_s2, Pull::Up);
-
-    let led_0 = Output::new(pin_led0, Level::Low);
-    let led_1 = Output::new(pin_led1, Level::Low);
-    let led_2 = Output::new(pin_led2, Level::Low);
-    let led_3 = Output::new(pin_led3, Level::Low);
-    let led_4 = Output::new(pin_led4, Level::Low);
-    let led_5 = Output::new(pin_led5, Level::Low);
-
     let joystick = JoystickRunner {
         adc,
-        vx_analog,
-        vy_analog,
-        vz_analog,
-        s1,
-        s2,
-        led_0,
-        led_1,
-        led_2,
-        led_3,
-        led_4,
-        led_5,
+        adc_channels,
+        temperature,
+        buttons,
+        debouncer: Debouncer::new(),
+        encoders: Encoders::new(),
+        filters: [AxisFilter::new(); AXIS_COUNT],
+        combo: ComboWatch::new(),
+        remapper: Remapper::new(),
+        modes: ButtonModes::new(),
+        axis_buttons: AxisButtons::new(),
+        button_axes: ButtonAxes::new(),
+        hat: Hat::new(),
         writer,
+        layout,
         state,
     };
 
-    let responder = HidResponderRunner { reader };
+    let responder = HidResponderRunner {
+        reader,
+        handler: MyRequestHandler { layout, state },
+    };
 
     (joystick, responder)
 }
@@ -0,0 +1,270 @@
+//! Animations for the status LEDs.
+//!
+//! A [`Pattern`] is rendered into a [`Frame`] from nothing but the time since
+//! it started and the latest [`Inputs`], so every pattern can be stepped and
+//! checked without any hardware.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    axis::{AXIS_COUNT, AXIS_MAX},
+    host_reports::LED_COUNT,
+    inputs::Inputs,
+};
+
+/// Brightness of each LED, from 0 (off) to 255 (fully on).
+pub type Frame = [u8; LED_COUNT];
+
+pub const OFF: Frame = [0; LED_COUNT];
+
+/// Time between steps of [`Pattern::BinaryCounter`], in milliseconds.
+pub const COUNTER_STEP_MS: u64 = 250;
+/// Time each LED stays lit in [`Pattern::Chase`], in milliseconds.
+pub const CHASE_STEP_MS: u64 = 100;
+/// Length of one full breath in [`Pattern::Breathe`], in milliseconds.
+pub const BREATHE_PERIOD_MS: u64 = 2000;
+/// Time each flash of [`Pattern::ErrorCode`] spends on, and then off.
+pub const ERROR_FLASH_MS: u64 = 200;
+/// Dark gap between repeats of [`Pattern::ErrorCode`], in milliseconds.
+pub const ERROR_PAUSE_MS: u64 = 1000;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Pattern {
+    /// Counts up in binary, LED 0 being the lowest bit.
+    #[default]
+    BinaryCounter,
+    /// A single lit LED running along the row.
+    Chase,
+    /// All LEDs fading up and down together.
+    Breathe,
+    /// A bar graph of the axis at this index, filling from LED 0.
+    AxisBar(u8),
+    /// Each LED lights while the button with the same index is held.
+    ButtonEcho,
+    /// All LEDs flash this many times, then pause, over and over.
+    ErrorCode(u8),
+}
+
+impl Pattern {
+    pub fn is_valid(&self) -> bool {
+        match *self {
+            Pattern::AxisBar(axis) => (axis as usize) < AXIS_COUNT,
+            Pattern::ErrorCode(code) => code > 0,
+            _ => true,
+        }
+    }
+
+    /// The frame to show `t_ms` milliseconds after the pattern started.
+    pub fn render(&self, t_ms: u64, inputs: &Inputs) -> Frame {
+        match *self {
+            Pattern::BinaryCounter => {
+                let count = t_ms / COUNTER_STEP_MS;
+                core::array::from_fn(|i| lit(count & (1 << i) != 0))
+            }
+            Pattern::Chase => {
+                let current = (t_ms / CHASE_STEP_MS) as usize % LED_COUNT;
+                core::array::from_fn(|i| lit(i == current))
+            }
+            Pattern::Breathe => {
+                let half = BREATHE_PERIOD_MS / 2;
+                let phase = t_ms % BREATHE_PERIOD_MS;
+                let rise = if phase < half {
+                    phase
+                } else {
+                    BREATHE_PERIOD_MS - phase
+                };
+                [(rise * 255 / half) as u8; LED_COUNT]
+            }
+            Pattern::AxisBar(axis) => {
+                let Some(&value) = inputs.axes.get(axis as usize) else {
+                    return OFF;
+                };
+                // How far along the row the bar reaches, in units of one
+                // LED's brightness, so the last LED shows the remainder.
+                let span = 2 * AXIS_MAX as i32;
+                let filled = (value as i32 + AXIS_MAX as i32) * (LED_COUNT as i32 * 255) / span;
+                core::array::from_fn(|i| (filled - i as i32 * 255).clamp(0, 255) as u8)
+            }
+            Pattern::ButtonEcho => {
+                core::array::from_fn(|i| lit(inputs.buttons.get(i).is_some_and(|&held| held)))
+            }
+            Pattern::ErrorCode(code) => {
+                let flashes = code as u64 * 2 * ERROR_FLASH_MS;
+                let phase = t_ms % (flashes + ERROR_PAUSE_MS);
+                let on = phase < flashes && (phase / ERROR_FLASH_MS) % 2 == 0;
+                [lit(on); LED_COUNT]
+            }
+        }
+    }
+}
+
+fn lit(on: bool) -> u8 {
+    if on {
+        255
+    } else {
+        0
+    }
+}
+
+/// Full scale of a PWM duty cycle; the LED slices count up to this.
+pub const DUTY_MAX: u16 = u16::MAX;
+
+/// Perceived brightness to PWM duty cycle, following a 2.5 power curve so
+/// that equal steps in level look like equal steps in brightness.
+static GAMMA: [u16; 256] = gamma_table();
+
+const fn gamma_table() -> [u16; 256] {
+    // x^2.5 is x^2 * sqrt(x). Square roots are taken of the level shifted up
+    // by 16 bits to keep their precision.
+    const fn isqrt(n: u64) -> u64 {
+        let mut root = 0;
+        let mut bit = 1 << 62;
+        let mut n = n;
+        while bit > n {
+            bit >>= 2;
+        }
+        while bit != 0 {
+            if n >= root + bit {
+                n -= root + bit;
+                root = (root >> 1) + bit;
+            } else {
+                root >>= 1;
+            }
+            bit >>= 2;
+        }
+        root
+    }
+    let full = 255 * 255 * isqrt(255 << 16);
+    let mut table = [0; 256];
+    let mut level = 0;
+    while level < 256 {
+        let x = level as u64;
+        table[level] = (x * x * isqrt(x << 16) * DUTY_MAX as u64 / full) as u16;
+        level += 1;
+    }
+    table
+}
+
+/// The duty cycle for an LED at `level` in a frame, dimmed to `brightness`.
+/// Both run from 0 to 255.
+pub fn duty(level: u8, brightness: u8) -> u16 {
+    let dimmed = level as u16 * brightness as u16 / 255;
+    GAMMA[dimmed as usize]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const X: u8 = 255;
+
+    fn render(pattern: Pattern, t_ms: u64) -> Frame {
+        pattern.render(t_ms, &Inputs::default())
+    }
+
+    #[test]
+    fn counter_counts_in_binary() {
+        assert_eq!(render(Pattern::BinaryCounter, 0), OFF);
+        assert_eq!(
+            render(Pattern::BinaryCounter, COUNTER_STEP_MS * 5),
+            [X, 0, X, 0, 0, 0]
+        );
+        assert_eq!(render(Pattern::BinaryCounter, COUNTER_STEP_MS * 63), [X; 6]);
+        // And wraps around once every LED is lit.
+        assert_eq!(render(Pattern::BinaryCounter, COUNTER_STEP_MS * 64), OFF);
+    }
+
+    #[test]
+    fn chase_moves_one_led_at_a_time() {
+        assert_eq!(render(Pattern::Chase, 0), [X, 0, 0, 0, 0, 0]);
+        assert_eq!(
+            render(Pattern::Chase, CHASE_STEP_MS * 3 + 1),
+            [0, 0, 0, X, 0, 0]
+        );
+        assert_eq!(
+            render(Pattern::Chase, CHASE_STEP_MS * 6),
+            [X, 0, 0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn breathe_fades_up_and_back_down() {
+        assert_eq!(render(Pattern::Breathe, 0), OFF);
+        assert_eq!(render(Pattern::Breathe, BREATHE_PERIOD_MS / 4), [127; 6]);
+        assert_eq!(render(Pattern::Breathe, BREATHE_PERIOD_MS / 2), [X; 6]);
+        assert_eq!(
+            render(Pattern::Breathe, BREATHE_PERIOD_MS * 3 / 4),
+            [127; 6]
+        );
+        assert_eq!(render(Pattern::Breathe, BREATHE_PERIOD_MS), OFF);
+    }
+
+    #[test]
+    fn axis_bar_follows_its_axis() {
+        let mut inputs = Inputs::default();
+        let bar = Pattern::AxisBar(1);
+
+        inputs.axes[1] = -AXIS_MAX;
+        assert_eq!(bar.render(0, &inputs), OFF);
+        inputs.axes[1] = 0;
+        assert_eq!(bar.render(0, &inputs), [X, X, X, 0, 0, 0]);
+        inputs.axes[1] = AXIS_MAX;
+        assert_eq!(bar.render(0, &inputs), [X; 6]);
+
+        // Part way into an LED lights it part way.
+        inputs.axes[1] = AXIS_MAX / 6;
+        assert_eq!(bar.render(0, &inputs), [X, X, X, 127, 0, 0]);
+
+        // Other axes have no effect.
+        inputs.axes[0] = AXIS_MAX;
+        assert_eq!(bar.render(0, &inputs), [X, X, X, 127, 0, 0]);
+    }
+
+    #[test]
+    fn button_echo_mirrors_the_buttons() {
+        let mut inputs = Inputs::default();
+        inputs.buttons[1] = true;
+        assert_eq!(Pattern::ButtonEcho.render(0, &inputs), [0, X, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn error_code_flashes_then_pauses() {
+        let code = Pattern::ErrorCode(2);
+        let frames: Vec<bool> = (0..2 * 4 + 5)
+            .map(|step| render(code, step * ERROR_FLASH_MS)[0] == X)
+            .collect();
+        assert_eq!(
+            frames,
+            [
+                true, false, true, false, // two flashes
+                false, false, false, false, false, // the pause
+                true, false, true, false, // and again
+            ]
+        );
+        assert!(render(code, 0).iter().all(|&led| led == X));
+    }
+
+    #[test]
+    fn gamma_spans_the_whole_duty_range() {
+        assert_eq!(duty(0, 255), 0);
+        assert_eq!(duty(255, 255), DUTY_MAX);
+        // Half the level is well under half the power.
+        assert!(duty(128, 255) < DUTY_MAX / 4);
+        assert!(GAMMA.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+
+    #[test]
+    fn brightness_dims_every_level() {
+        assert_eq!(duty(255, 0), 0);
+        assert_eq!(duty(255, 128), duty(128, 255));
+        assert_eq!(duty(100, 255), duty(255, 100));
+    }
+
+    #[test]
+    fn rejects_patterns_with_bad_parameters() {
+        assert!(Pattern::AxisBar(AXIS_COUNT as u8 - 1).is_valid());
+        assert!(!Pattern::AxisBar(AXIS_COUNT as u8).is_valid());
+        assert!(!Pattern::ErrorCode(0).is_valid());
+        assert!(Pattern::ErrorCode(3).is_valid());
+    }
+}
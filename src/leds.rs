@@ -0,0 +1,133 @@
+use defmt::warn;
+use embassy_futures::select::{select, Either};
+use embassy_rp::{
+    peripherals::{PIO0, PWM_SLICE1, PWM_SLICE2, PWM_SLICE3},
+    pwm::{self, ChannelAPin, ChannelBPin, Pwm, PwmOutput, SetDutyCycle},
+};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel, mutex::Mutex};
+use embassy_time::{Instant, Timer};
+use usb_joystick::{
+    config::Config,
+    host_reports::{self, LedMode, LED_COUNT},
+    led_patterns::{self, Pattern},
+    pixels::MAX_PIXELS,
+};
+
+use crate::{state::SharedState, ws2812::Ws2812};
+
+/// Time between frames, in milliseconds. Fast enough for a smooth fade.
+const FRAME_MS: u64 = 10;
+
+pub enum LedCommand {
+    /// Switch to another animation, starting it from the beginning.
+    Pattern(Pattern),
+    /// LED modes set by the host through the output report.
+    Host([LedMode; LED_COUNT]),
+}
+
+static COMMANDS: Channel<CriticalSectionRawMutex, LedCommand, 4> = Channel::new();
+
+/// Hands a command to the LED task. This never waits, so it can be called
+/// from sync code such as the USB request handler; if the queue is full the
+/// command is dropped.
+pub fn send(command: LedCommand) {
+    if COMMANDS.try_send(command).is_err() {
+        warn!("LED command queue full, dropping command");
+    }
+}
+
+/// Restarts the animation if a change of config picked a new pattern.
+pub fn config_changed(previous: &Config, config: &Config) {
+    if config.led_pattern != previous.led_pattern {
+        send(LedCommand::Pattern(config.led_pattern));
+    }
+}
+
+pub struct LedRunner {
+    leds: [PwmOutput<'static>; LED_COUNT],
+    strip: Ws2812<'static, PIO0>,
+    pattern: Pattern,
+    host: [LedMode; LED_COUNT],
+    state: &'static Mutex<CriticalSectionRawMutex, SharedState>,
+}
+
+impl LedRunner {
+    pub async fn run(&mut self) -> ! {
+        let mut started = Instant::now();
+
+        loop {
+            match select(Timer::after_millis(FRAME_MS), COMMANDS.receive()).await {
+                Either::First(()) => {}
+                Either::Second(LedCommand::Pattern(pattern)) => {
+                    self.pattern = pattern;
+                    started = Instant::now();
+                    continue;
+                }
+                Either::Second(LedCommand::Host(host)) => {
+                    self.host = host;
+                    continue;
+                }
+            }
+
+            let (config, inputs) = {
+                let state = self.state.lock().await;
+                (state.config, state.inputs)
+            };
+            let animation = if config.power {
+                self.pattern.render(started.elapsed().as_millis(), &inputs)
+            } else {
+                led_patterns::OFF
+            };
+            let frame =
+                host_reports::merge_leds(&self.host, &animation, Instant::now().as_millis());
+
+            let brightness = config.led_brightness;
+            for ((led, level), brightness) in self.leds.iter_mut().zip(frame).zip(brightness) {
+                // Duty cycles never exceed the default top of 0xffff.
+                _ = led.set_duty_cycle(led_patterns::duty(level, brightness));
+            }
+
+            let mut words = [0; MAX_PIXELS];
+            let count = config.pixels.render(&inputs, &frame, &mut words);
+            if !config.power {
+                words = [0; MAX_PIXELS];
+            }
+            self.strip.write(&words[..count]).await;
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn make_leds(
+    pwm_1: PWM_SLICE1,
+    pwm_2: PWM_SLICE2,
+    pwm_3: PWM_SLICE3,
+    pin_led0: impl ChannelAPin<PWM_SLICE1>,
+    pin_led1: impl ChannelBPin<PWM_SLICE1>,
+    pin_led2: impl ChannelAPin<PWM_SLICE2>,
+    pin_led3: impl ChannelBPin<PWM_SLICE2>,
+    pin_led4: impl ChannelAPin<PWM_SLICE3>,
+    pin_led5: impl ChannelBPin<PWM_SLICE3>,
+    strip: Ws2812<'static, PIO0>,
+    pattern: Pattern,
+    state: &'static Mutex<CriticalSectionRawMutex, SharedState>,
+) -> LedRunner {
+    // Each slice drives a pair of LEDs, counting to 0xffff at the full system
+    // clock, which is around 1.9 kHz and well clear of visible flicker.
+    let (led_0, led_1) =
+        Pwm::new_output_ab(pwm_1, pin_led0, pin_led1, pwm::Config::default()).split();
+    let (led_2, led_3) =
+        Pwm::new_output_ab(pwm_2, pin_led2, pin_led3, pwm::Config::default()).split();
+    let (led_4, led_5) =
+        Pwm::new_output_ab(pwm_3, pin_led4, pin_led5, pwm::Config::default()).split();
+    // Both channels of every slice were given a pin, so none of these are None.
+    let leds = [led_0, led_1, led_2, led_3, led_4, led_5].map(|led| led.unwrap());
+
+    LedRunner {
+        leds,
+        strip,
+        pattern,
+        host: [LedMode::Auto; LED_COUNT],
+        state,
+    }
+}
@@ -0,0 +1,28 @@
+//! Hardware-independent parts of the joystick firmware.
+//!
+//! Everything in here builds for the host as well as the RP2040, so it can be
+//! unit tested with `cargo test --lib --target <host triple>`.
+#![cfg_attr(not(test), no_std)]
+
+pub mod axis;
+pub mod button_modes;
+pub mod calibration;
+pub mod config;
+pub mod config_store;
+pub mod curves;
+pub mod debounce;
+pub mod encoders;
+pub mod filters;
+pub mod hat;
+pub mod hid_descriptor;
+pub mod host_reports;
+pub mod idle;
+pub mod inputs;
+pub mod led_patterns;
+pub mod matrix;
+pub mod pixels;
+pub mod profiles;
+pub mod remap;
+pub mod sensors;
+pub mod sources;
+pub mod virtual_controls;
@@ -1,14 +1,21 @@
 #![no_std]
 #![no_main]
 #![feature(impl_trait_in_assoc_type)]
+// The web router's type nests once per route, deeper than the default allows.
+#![recursion_limit = "256"]
 
-mod hid_descriptor;
+mod buttons;
 mod joystick;
+mod leds;
 mod network;
+mod rotary;
+mod sensor_bus;
 mod state;
+mod storage;
 mod usb_device;
 mod usb_ethernet;
 mod web;
+mod ws2812;
 
 static DEVICE_NAME: &str = "Custom Joystick";
 static DEVICE_HOST: &str = "joystick";
@@ -19,6 +26,7 @@ static DNS_SERVERS: [Ipv4Addr; 1] = [OUR_IP];
 const MTU: usize = 1514;
 
 use {
+    buttons::Buttons,
     core::net::Ipv4Addr,
     defmt::info,
     defmt_rtt as _,
@@ -26,9 +34,10 @@ use {
     embassy_rp::{
         adc, bind_interrupts,
         clocks::RoscRng,
-        gpio::{AnyPin, Level, Output},
+        gpio::{AnyPin, Level, Output, Pull},
         i2c::InterruptHandler,
-        peripherals::{I2C1, USB},
+        peripherals::{I2C1, PIO0, PIO1, USB},
+        pio,
         usb::{self, Driver},
     },
     embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, mutex::Mutex},
@@ -39,12 +48,15 @@ use {
     picoserve::make_static,
     rand::RngCore,
     state::{AppState, SharedState},
+    usb_joystick::{idle::IdleRate, inputs::Inputs},
 };
 
 bind_interrupts!(struct Irqs {
     I2C1_IRQ => InterruptHandler<I2C1>;
     USBCTRL_IRQ => usb::InterruptHandler<USB>;
     ADC_IRQ_FIFO => adc::InterruptHandler;
+    PIO0_IRQ_0 => pio::InterruptHandler<PIO0>;
+    PIO1_IRQ_0 => pio::InterruptHandler<PIO1>;
 });
 
 #[embassy_executor::main]
@@ -52,7 +64,16 @@ async fn main(spawner: Spawner) {
     let p = embassy_rp::init(Default::default());
     let led = Output::new(AnyPin::from(p.PIN_22), Level::Low);
 
-    let shared_state = make_static!(Mutex<CriticalSectionRawMutex, SharedState>, Mutex::new(SharedState { power: true }));
+    let mut config_store = storage::make_config_store(p.FLASH);
+    let device_config = storage::load_config(&mut config_store);
+
+    let shared_state = make_static!(Mutex<CriticalSectionRawMutex, SharedState>, Mutex::new(SharedState {
+        config: device_config,
+        layout: device_config.report_layout(),
+        capture: None,
+        inputs: Inputs::default(),
+        idle: IdleRate::Unset,
+    }));
 
     Timer::after_millis(100).await;
 
@@ -65,20 +86,64 @@ async fn main(spawner: Spawner) {
     let mut builder = usb_device::get_usb_builder(usb_driver);
     let (ncm_runner, device) = usb_ethernet::make_usb_ethernet_device(&mut builder);
     let (net_runner, stack) = network::make_network_stack(device, seed);
+    // Buttons 1, 2, ... in order. Add pins here to wire up more buttons.
+    #[cfg(not(feature = "button-matrix"))]
+    let buttons = Buttons::direct([p.PIN_20.into(), p.PIN_21.into()]);
+    // A 4x4 matrix with a diode on each switch.
+    #[cfg(feature = "button-matrix")]
+    let buttons = Buttons::matrix(
+        [
+            p.PIN_8.into(),
+            p.PIN_9.into(),
+            p.PIN_10.into(),
+            p.PIN_11.into(),
+        ],
+        [
+            p.PIN_12.into(),
+            p.PIN_13.into(),
+            p.PIN_14.into(),
+            p.PIN_15.into(),
+        ],
+        true,
+    );
     let (joystick_runner, hid_runner) = joystick::make_joystick(
         &mut builder,
-        embassy_rp::adc::Adc::new(p.ADC, Irqs, embassy_rp::adc::Config::default()),
-        p.PIN_26,
-        p.PIN_27,
-        p.PIN_28,
-        p.PIN_20,
-        p.PIN_21,
+        adc::Adc::new(p.ADC, Irqs, adc::Config::default()),
+        [
+            adc::Channel::new_pin(p.PIN_26, Pull::None),
+            adc::Channel::new_pin(p.PIN_27, Pull::None),
+            adc::Channel::new_pin(p.PIN_28, Pull::None),
+            adc::Channel::new_pin(p.PIN_29, Pull::None),
+        ],
+        adc::Channel::new_temp_sensor(p.ADC_TEMP_SENSOR),
+        buttons,
+        device_config.report_layout(),
+        shared_state,
+    );
+    let led_runner = leds::make_leds(
+        p.PWM_SLICE1,
+        p.PWM_SLICE2,
+        p.PWM_SLICE3,
         p.PIN_2,
         p.PIN_3,
         p.PIN_4,
         p.PIN_5,
         p.PIN_6,
         p.PIN_7,
+        ws2812::Ws2812::new(p.PIO0, Irqs, p.DMA_CH0, p.PIN_16),
+        device_config.led_pattern,
+        shared_state,
+    );
+    // The second encoder takes two pins the buttons leave free.
+    #[cfg(not(feature = "button-matrix"))]
+    let encoder_pins = (p.PIN_12, p.PIN_13);
+    #[cfg(feature = "button-matrix")]
+    let encoder_pins = (p.PIN_20, p.PIN_21);
+    let encoder_runner = rotary::make_encoders(p.PIO1, Irqs, (p.PIN_0, p.PIN_1), encoder_pins);
+    let mut i2c_config = embassy_rp::i2c::Config::default();
+    i2c_config.frequency = 400_000;
+    let sensor_runner = sensor_bus::SensorRunner::new(
+        embassy_rp::i2c::I2c::new_async(p.I2C1, p.PIN_19, p.PIN_18, Irqs, i2c_config),
         shared_state,
     );
     let usb = builder.build();
@@ -115,12 +180,28 @@ async fn main(spawner: Spawner) {
     }
     info!("Web task started");
 
+    spawner.must_spawn(storage::storage_task(
+        config_store,
+        shared_state,
+        device_config,
+    ));
+    info!("Storage task started");
+
     spawner.must_spawn(hid_task(hid_runner));
     info!("HID task started");
 
     spawner.must_spawn(joystick_task(joystick_runner));
     info!("Joystick task started");
 
+    spawner.must_spawn(led_task(led_runner));
+    info!("LED task started");
+
+    spawner.must_spawn(encoder_task(encoder_runner));
+    info!("Encoder task started");
+
+    spawner.must_spawn(sensor_task(sensor_runner));
+    info!("Sensor task started");
+
     loop {
         Timer::after(Duration::from_secs(3)).await;
     }
@@ -162,3 +243,18 @@ async fn hid_task(runner: joystick::HidResponderRunner<'static, Driver<'static,
 async fn joystick_task(mut runner: JoystickRunner<Driver<'static, USB>>) -> ! {
     runner.run().await
 }
+
+#[embassy_executor::task]
+async fn led_task(mut runner: leds::LedRunner) -> ! {
+    runner.run().await
+}
+
+#[embassy_executor::task]
+async fn encoder_task(mut runner: rotary::EncoderRunner) -> ! {
+    runner.run().await
+}
+
+#[embassy_executor::task]
+async fn sensor_task(mut runner: sensor_bus::SensorRunner) -> ! {
+    runner.run().await
+}
@@ -0,0 +1,137 @@
+//! Turns scans of a row/column button matrix into button states.
+//!
+//! Each scan drives one row at a time and reads back which columns see it,
+//! giving one column mask per row. With a diode on every switch that is the
+//! whole story. Without them, current can sneak back through three closed
+//! switches on the corners of a rectangle and make the fourth corner read as
+//! closed too, so any such rectangle is treated as unknown and its switches
+//! keep their previous state.
+
+use crate::inputs::BUTTON_COUNT;
+
+/// Most rows a matrix can have.
+pub const MAX_ROWS: usize = 8;
+
+pub struct Matrix {
+    rows: usize,
+    cols: usize,
+    diodes: bool,
+    state: [u32; MAX_ROWS],
+}
+
+impl Matrix {
+    /// A matrix of `rows` by `cols` switches, numbered across each row in
+    /// turn. All of them must fit in the report.
+    pub fn new(rows: usize, cols: usize, diodes: bool) -> Self {
+        assert!(rows <= MAX_ROWS && rows * cols <= BUTTON_COUNT);
+        Self {
+            rows,
+            cols,
+            diodes,
+            state: [0; MAX_ROWS],
+        }
+    }
+
+    /// Takes one column mask per row, bit `c` set when the switch in column
+    /// `c` reads closed, and updates the state from it.
+    pub fn update(&mut self, scan: &[u32]) {
+        let col_mask = u32::MAX >> (32 - self.cols.max(1));
+        let mut scan: [u32; MAX_ROWS] =
+            core::array::from_fn(|r| scan.get(r).map_or(0, |cols| cols & col_mask));
+
+        if !self.diodes {
+            // Switches on the corners of a rectangle can't be told apart from
+            // a ghost, so they keep what they were.
+            let mut unknown = [0; MAX_ROWS];
+            for r in 0..self.rows {
+                for s in r + 1..self.rows {
+                    let shared = scan[r] & scan[s];
+                    if shared.count_ones() >= 2 {
+                        unknown[r] |= shared;
+                        unknown[s] |= shared;
+                    }
+                }
+            }
+            for r in 0..self.rows {
+                scan[r] = (scan[r] & !unknown[r]) | (self.state[r] & unknown[r]);
+            }
+        }
+
+        self.state = scan;
+    }
+
+    /// The state of every button in the report, those past the end of the
+    /// matrix being released.
+    pub fn buttons(&self) -> [bool; BUTTON_COUNT] {
+        core::array::from_fn(|i| {
+            let (r, c) = (i / self.cols.max(1), i % self.cols.max(1));
+            r < self.rows && self.state[r] & (1 << c) != 0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pressed(matrix: &Matrix) -> Vec<usize> {
+        let buttons = matrix.buttons();
+        (0..BUTTON_COUNT).filter(|&i| buttons[i]).collect()
+    }
+
+    #[test]
+    fn switches_are_numbered_across_the_rows() {
+        let mut matrix = Matrix::new(3, 4, true);
+        matrix.update(&[0b0001, 0b0100, 0b1000]);
+        assert_eq!(pressed(&matrix), [0, 6, 11]);
+    }
+
+    #[test]
+    fn columns_past_the_edge_are_ignored() {
+        let mut matrix = Matrix::new(2, 3, true);
+        matrix.update(&[0b1001, 0]);
+        assert_eq!(pressed(&matrix), [0]);
+    }
+
+    #[test]
+    fn diodes_make_every_combination_readable() {
+        let mut matrix = Matrix::new(2, 2, true);
+        matrix.update(&[0b11, 0b11]);
+        assert_eq!(pressed(&matrix), [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn ghost_on_the_fourth_corner_is_blocked() {
+        let mut matrix = Matrix::new(2, 3, false);
+        matrix.update(&[0b011, 0]);
+        assert_eq!(pressed(&matrix), [0, 1]);
+
+        // A third corner goes down, and the fourth reads closed with it.
+        matrix.update(&[0b011, 0b011]);
+        assert_eq!(pressed(&matrix), [0, 1]);
+
+        // Switches outside the rectangle are still read.
+        matrix.update(&[0b011, 0b111]);
+        assert_eq!(pressed(&matrix), [0, 1, 5]);
+
+        // Once the rectangle is broken, everything reads true again.
+        matrix.update(&[0b001, 0b101]);
+        assert_eq!(pressed(&matrix), [0, 3, 5]);
+    }
+
+    #[test]
+    fn held_switches_stay_held_inside_a_rectangle() {
+        let mut matrix = Matrix::new(2, 2, false);
+        matrix.update(&[0b01, 0b01]);
+        assert_eq!(pressed(&matrix), [0, 2]);
+
+        matrix.update(&[0b11, 0b11]);
+        assert_eq!(pressed(&matrix), [0, 2]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn matrix_must_fit_the_report() {
+        Matrix::new(5, 7, true);
+    }
+}
@@ -52,7 +52,7 @@ where
 }
 
 #[embassy_executor::task]
-pub async fn dhcp_task(stack: Stack<'static>) -> () {
+pub async fn dhcp_task(stack: Stack<'static>) {
     let mut buf = [0; 1500];
 
     let buffers: UdpBuffers<1, 1500, 1500, 2> = UdpBuffers::new();
@@ -82,7 +82,7 @@ pub async fn dhcp_task(stack: Stack<'static>) -> () {
 }
 
 #[embassy_executor::task]
-pub async fn captive_dns_task(stack: Stack<'static>) -> () {
+pub async fn captive_dns_task(stack: Stack<'static>) {
     let mut tx_buf: [u8; 1500] = [0; 1500];
     let mut rx_buf: [u8; 1500] = [0; 1500];
     let ip = Ipv4Addr::new(10, 42, 0, 1);
@@ -103,7 +103,7 @@ pub async fn captive_dns_task(stack: Stack<'static>) -> () {
 }
 
 #[embassy_executor::task]
-pub async fn mdns_task(stack: Stack<'static>) -> () {
+pub async fn mdns_task(stack: Stack<'static>) {
     let (recv_buf, send_buf) = (
         VecBufAccess::<NoopRawMutex, 1500>::new(),
         VecBufAccess::<NoopRawMutex, 1500>::new(),
@@ -0,0 +1,263 @@
+//! Colours for a strip of WS2812 addressable LEDs.
+//!
+//! Each pixel follows a [`PixelSource`]: a fixed colour, a button, an axis,
+//! or one of the status LEDs, so the strip can show the LED patterns too.
+//! Working out the colours is kept apart from the PIO driver so that it
+//! can be tested on the host.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    axis::{AXIS_COUNT, AXIS_MAX},
+    host_reports::LED_COUNT,
+    inputs::{Inputs, BUTTON_COUNT},
+    led_patterns::Frame,
+};
+
+/// Most pixels the strip can have. The DMA buffer is sized for this many.
+pub const MAX_PIXELS: usize = 8;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub const BLACK: Rgb = Rgb::new(0, 0, 0);
+
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    /// The colour `t / span` of the way from `self` to `other`.
+    pub fn blend(self, other: Rgb, t: u32, span: u32) -> Rgb {
+        let channel = |from: u8, to: u8| {
+            let (from, to) = (from as i32, to as i32);
+            (from + (to - from) * t as i32 / span as i32) as u8
+        };
+        Rgb::new(
+            channel(self.r, other.r),
+            channel(self.g, other.g),
+            channel(self.b, other.b),
+        )
+    }
+
+    /// The 24-bit word a WS2812 expects, in green-red-blue order and shifted
+    /// to the top of the word, as the PIO program sends the most significant
+    /// bit first.
+    pub fn to_grb_word(self) -> u32 {
+        (self.g as u32) << 24 | (self.r as u32) << 16 | (self.b as u32) << 8
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PixelSource {
+    /// Always shows the `on` colour.
+    Fixed,
+    /// Shows `on` while the button at this index is held, `off` otherwise.
+    Button(u8),
+    /// Fades from `off` at one end of the axis at this index to `on` at the
+    /// other.
+    Axis(u8),
+    /// Fades from `off` to `on` as the status LED at this index brightens,
+    /// whether the LED pattern or the host is lighting it.
+    Led(u8),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Pixel {
+    pub source: PixelSource,
+    pub off: Rgb,
+    pub on: Rgb,
+}
+
+impl Pixel {
+    pub fn is_valid(&self) -> bool {
+        match self.source {
+            PixelSource::Fixed => true,
+            PixelSource::Button(button) => (button as usize) < BUTTON_COUNT,
+            PixelSource::Axis(axis) => (axis as usize) < AXIS_COUNT,
+            PixelSource::Led(led) => (led as usize) < LED_COUNT,
+        }
+    }
+
+    /// This pixel's colour, given the inputs and the frame on the status
+    /// LEDs.
+    pub fn color(&self, inputs: &Inputs, leds: &Frame) -> Rgb {
+        match self.source {
+            PixelSource::Fixed => self.on,
+            PixelSource::Button(button) => match inputs.buttons.get(button as usize) {
+                Some(true) => self.on,
+                _ => self.off,
+            },
+            PixelSource::Axis(axis) => {
+                let Some(&value) = inputs.axes.get(axis as usize) else {
+                    return self.off;
+                };
+                let t = (value as i32 + AXIS_MAX as i32).max(0) as u32;
+                self.off.blend(self.on, t, 2 * AXIS_MAX as u32)
+            }
+            PixelSource::Led(led) => match leds.get(led as usize) {
+                Some(&level) => self.off.blend(self.on, level as u32, u8::MAX as u32),
+                None => self.off,
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PixelConfig {
+    /// Number of pixels fitted, up to [`MAX_PIXELS`]. Zero if there is no
+    /// strip.
+    pub count: u8,
+    pub pixels: [Pixel; MAX_PIXELS],
+}
+
+impl PixelConfig {
+    pub const fn new() -> Self {
+        const GREEN: Rgb = Rgb::new(0, 64, 0);
+        const BLUE: Rgb = Rgb::new(0, 0, 64);
+        const RED: Rgb = Rgb::new(64, 0, 0);
+        const fn button(index: u8) -> Pixel {
+            Pixel {
+                source: PixelSource::Button(index),
+                off: Rgb::BLACK,
+                on: GREEN,
+            }
+        }
+        const fn axis(index: u8) -> Pixel {
+            Pixel {
+                source: PixelSource::Axis(index),
+                off: BLUE,
+                on: RED,
+            }
+        }
+        const FIXED: Pixel = Pixel {
+            source: PixelSource::Fixed,
+            off: Rgb::BLACK,
+            on: Rgb::BLACK,
+        };
+        Self {
+            count: 0,
+            pixels: [
+                button(0),
+                button(1),
+                axis(0),
+                axis(1),
+                axis(2),
+                axis(3),
+                FIXED,
+                FIXED,
+            ],
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.count as usize <= MAX_PIXELS && self.pixels.iter().all(Pixel::is_valid)
+    }
+
+    /// Fills `words` with the data for each fitted pixel and returns how many
+    /// there are.
+    pub fn render(&self, inputs: &Inputs, leds: &Frame, words: &mut [u32; MAX_PIXELS]) -> usize {
+        let count = (self.count as usize).min(MAX_PIXELS);
+        for (word, pixel) in words.iter_mut().zip(&self.pixels[..count]) {
+            *word = pixel.color(inputs, leds).to_grb_word();
+        }
+        count
+    }
+}
+
+impl Default for PixelConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::led_patterns::OFF;
+
+    const WHITE: Rgb = Rgb::new(255, 255, 255);
+
+    fn pixel(source: PixelSource) -> Pixel {
+        Pixel {
+            source,
+            off: Rgb::new(0, 0, 100),
+            on: Rgb::new(200, 0, 0),
+        }
+    }
+
+    #[test]
+    fn words_are_green_red_blue() {
+        assert_eq!(Rgb::new(0x12, 0x34, 0x56).to_grb_word(), 0x3412_5600);
+    }
+
+    #[test]
+    fn blend_runs_between_the_two_colours() {
+        assert_eq!(Rgb::BLACK.blend(WHITE, 0, 10), Rgb::BLACK);
+        assert_eq!(Rgb::BLACK.blend(WHITE, 10, 10), WHITE);
+        assert_eq!(WHITE.blend(Rgb::BLACK, 5, 10), Rgb::new(128, 128, 128));
+    }
+
+    #[test]
+    fn button_pixels_follow_their_button() {
+        let mut inputs = Inputs::default();
+        let p = pixel(PixelSource::Button(1));
+        assert_eq!(p.color(&inputs, &OFF), p.off);
+        inputs.buttons[1] = true;
+        assert_eq!(p.color(&inputs, &OFF), p.on);
+    }
+
+    #[test]
+    fn axis_pixels_fade_across_the_range() {
+        let mut inputs = Inputs::default();
+        let p = pixel(PixelSource::Axis(2));
+        inputs.axes[2] = -AXIS_MAX;
+        assert_eq!(p.color(&inputs, &OFF), p.off);
+        inputs.axes[2] = AXIS_MAX;
+        assert_eq!(p.color(&inputs, &OFF), p.on);
+        inputs.axes[2] = 0;
+        assert_eq!(p.color(&inputs, &OFF), Rgb::new(100, 0, 50));
+    }
+
+    #[test]
+    fn led_pixels_follow_the_status_leds() {
+        let inputs = Inputs::default();
+        let p = pixel(PixelSource::Led(4));
+        assert_eq!(p.color(&inputs, &OFF), p.off);
+        let mut leds = OFF;
+        leds[4] = u8::MAX;
+        assert_eq!(p.color(&inputs, &leds), p.on);
+        leds[4] = 128;
+        assert_eq!(p.color(&inputs, &leds), Rgb::new(100, 0, 50));
+    }
+
+    #[test]
+    fn only_fitted_pixels_are_rendered() {
+        let mut config = PixelConfig::new();
+        config.count = 3;
+        config.pixels[0] = pixel(PixelSource::Fixed);
+
+        let mut words = [0xdead_beef; MAX_PIXELS];
+        assert_eq!(config.render(&Inputs::default(), &OFF, &mut words), 3);
+        assert_eq!(words[0], Rgb::new(200, 0, 0).to_grb_word());
+        assert_eq!(words[3..], [0xdead_beef; MAX_PIXELS - 3]);
+    }
+
+    #[test]
+    fn rejects_missing_inputs_and_long_strips() {
+        let mut config = PixelConfig::new();
+        assert!(config.is_valid());
+        config.pixels[4].source = PixelSource::Button(BUTTON_COUNT as u8);
+        assert!(!config.is_valid());
+        config.pixels[4].source = PixelSource::Led(LED_COUNT as u8);
+        assert!(!config.is_valid());
+
+        let mut config = PixelConfig::new();
+        config.count = MAX_PIXELS as u8 + 1;
+        assert!(!config.is_valid());
+    }
+}
@@ -0,0 +1,326 @@
+//! Named sets of settings that can be swapped in and out together.
+//!
+//! The settings in use live at the top level of [`Config`]; a [`Profile`]
+//! holds a copy of the ones that differ from game to game. Switching profiles
+//! copies the settings in use back into the profile they came from, then
+//! copies the next profile's settings over them.
+//!
+//! A profile's axis sources and encoders can move an axis to another input,
+//! but not add or remove one: the host is told the report's layout once, at
+//! startup, so profiles that would change it are refused.
+
+use core::fmt;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    axis::AXIS_COUNT,
+    button_modes::ButtonMode,
+    config::Config,
+    curves::Curve,
+    encoders::{EncoderConfig, ENCODER_COUNT},
+    hat::HatConfig,
+    hid_descriptor::ReportLayout,
+    host_reports::LED_COUNT,
+    inputs::BUTTON_COUNT,
+    led_patterns::Pattern,
+    pixels::PixelConfig,
+    remap::ButtonMap,
+    sources::AxisSource,
+    virtual_controls::{AxisButton, ButtonAxis, AXIS_BUTTON_COUNT, BUTTON_AXIS_COUNT},
+};
+
+/// Number of profiles kept.
+pub const PROFILE_COUNT: usize = 4;
+
+/// Longest profile name, in bytes.
+pub const NAME_LEN: usize = 16;
+
+/// Most buttons a combo can take.
+pub const MAX_COMBO_BUTTONS: usize = 3;
+
+/// A profile's name, stored inline so that [`Config`] stays `Copy`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct ProfileName {
+    len: u8,
+    bytes: [u8; NAME_LEN],
+}
+
+impl ProfileName {
+    /// `None` if `name` is longer than [`NAME_LEN`] bytes.
+    pub fn new(name: &str) -> Option<Self> {
+        let mut bytes = [0; NAME_LEN];
+        bytes
+            .get_mut(..name.len())?
+            .copy_from_slice(name.as_bytes());
+        Some(Self {
+            len: name.len() as u8,
+            bytes,
+        })
+    }
+
+    /// "Profile 1" for the profile at index 0, and so on.
+    pub fn numbered(index: usize) -> Self {
+        let mut name = Self::new("Profile 0").unwrap();
+        name.bytes[name.len as usize - 1] += index as u8 + 1;
+        name
+    }
+
+    pub fn as_str(&self) -> &str {
+        // Only ever built from a whole `str`.
+        core::str::from_utf8(&self.bytes[..self.len as usize]).unwrap_or_default()
+    }
+}
+
+impl fmt::Debug for ProfileName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl Serialize for ProfileName {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for ProfileName {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct NameVisitor;
+
+        impl de::Visitor<'_> for NameVisitor {
+            type Value = ProfileName;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a name of at most {NAME_LEN} bytes")
+            }
+
+            fn visit_str<E: de::Error>(self, name: &str) -> Result<ProfileName, E> {
+                ProfileName::new(name).ok_or_else(|| E::invalid_length(name.len(), &self))
+            }
+        }
+
+        deserializer.deserialize_str(NameVisitor)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Profile {
+    pub name: ProfileName,
+    pub invert: [bool; AXIS_COUNT],
+    /// Like [`Config::axis_sources`], but these must leave the same axes in
+    /// the report.
+    pub axis_sources: [AxisSource; AXIS_COUNT],
+    /// Deadzone of each axis, in raw ADC steps. The rest of the calibration
+    /// belongs to the hardware, so it's shared by every profile.
+    pub deadzones: [u16; AXIS_COUNT],
+    pub curves: [Curve; AXIS_COUNT],
+    pub button_map: ButtonMap,
+    pub button_modes: [ButtonMode; BUTTON_COUNT],
+    pub axis_buttons: [AxisButton; AXIS_BUTTON_COUNT],
+    pub button_axes: [ButtonAxis; BUTTON_AXIS_COUNT],
+    pub hat: HatConfig,
+    pub encoders: [EncoderConfig; ENCODER_COUNT],
+    pub led_pattern: Pattern,
+    pub led_brightness: [u8; LED_COUNT],
+    pub pixels: PixelConfig,
+}
+
+impl Profile {
+    /// The settings in use in `config`, under `name`.
+    pub fn capture(name: ProfileName, config: &Config) -> Self {
+        Self {
+            name,
+            invert: config.invert,
+            axis_sources: config.axis_sources,
+            deadzones: config.calibration.axes.map(|axis| axis.deadzone),
+            curves: config.curves,
+            button_map: config.button_map,
+            button_modes: config.button_modes,
+            axis_buttons: config.axis_buttons,
+            button_axes: config.button_axes,
+            hat: config.hat,
+            encoders: config.encoders,
+            led_pattern: config.led_pattern,
+            led_brightness: config.led_brightness,
+            pixels: config.pixels,
+        }
+    }
+
+    /// Copies this profile's settings into `config`. Deadzones are narrowed
+    /// to fit the calibration if they need to be.
+    pub fn apply(&self, config: &mut Config) {
+        config.invert = self.invert;
+        config.axis_sources = self.axis_sources;
+        for (axis, &deadzone) in config.calibration.axes.iter_mut().zip(&self.deadzones) {
+            axis.deadzone = deadzone.min(axis.max_deadzone());
+        }
+        config.curves = self.curves;
+        config.button_map = self.button_map;
+        config.button_modes = self.button_modes;
+        config.axis_buttons = self.axis_buttons;
+        config.button_axes = self.button_axes;
+        config.hat = self.hat;
+        config.encoders = self.encoders;
+        config.led_pattern = self.led_pattern;
+        config.led_brightness = self.led_brightness;
+        config.pixels = self.pixels;
+    }
+
+    /// The axes these settings put in the report.
+    pub fn report_layout(&self) -> ReportLayout {
+        ReportLayout::of(&self.axis_sources, &self.encoders)
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.axis_sources.iter().all(AxisSource::is_valid)
+            && self.curves.iter().all(Curve::is_valid)
+            && self.button_map.is_valid()
+            && self.button_modes.iter().all(ButtonMode::is_valid)
+            && self.axis_buttons.iter().all(AxisButton::is_valid)
+            && self.button_axes.iter().all(ButtonAxis::is_valid)
+            && self.hat.is_valid()
+            && self.encoders.iter().all(EncoderConfig::is_valid)
+            && self.led_pattern.is_valid()
+            && self.pixels.is_valid()
+    }
+}
+
+/// Buttons that, held together, switch to the next profile.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProfileCombo {
+    /// Number of buttons in the combo, 0 to turn it off.
+    pub count: u8,
+    pub buttons: [u8; MAX_COMBO_BUTTONS],
+}
+
+impl ProfileCombo {
+    pub const fn new() -> Self {
+        Self {
+            count: 0,
+            buttons: [0, 1, 2],
+        }
+    }
+
+    fn used(&self) -> &[u8] {
+        &self.buttons[..(self.count as usize).min(MAX_COMBO_BUTTONS)]
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.count as usize <= MAX_COMBO_BUTTONS
+            && self.used().iter().all(|&b| (b as usize) < BUTTON_COUNT)
+    }
+}
+
+impl Default for ProfileCombo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Watches for the profile combo being pressed.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ComboWatch {
+    held: bool,
+}
+
+impl ComboWatch {
+    pub const fn new() -> Self {
+        Self { held: false }
+    }
+
+    /// True once each time every button in the combo comes to be held.
+    pub fn update(&mut self, combo: &ProfileCombo, buttons: &[bool; BUTTON_COUNT]) -> bool {
+        let used = combo.used();
+        let held = !used.is_empty()
+            && used
+                .iter()
+                .all(|&b| buttons.get(b as usize).copied().unwrap_or(false));
+        let pressed = held && !self.held;
+        self.held = held;
+        pressed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_round_trip_through_json_and_postcard() {
+        let name = ProfileName::new("Flight sim").unwrap();
+        assert_eq!(name.as_str(), "Flight sim");
+
+        let mut buf = [0; 32];
+        let bytes = postcard::to_slice(&name, &mut buf).unwrap();
+        assert_eq!(postcard::from_bytes::<ProfileName>(bytes), Ok(name));
+
+        let mut buf = [0; 32];
+        let len = serde_json_core::to_slice(&name, &mut buf).unwrap();
+        assert_eq!(&buf[..len], b"\"Flight sim\"");
+        let (parsed, _) = serde_json_core::from_slice::<ProfileName>(&buf[..len]).unwrap();
+        assert_eq!(parsed, name);
+    }
+
+    #[test]
+    fn long_names_are_refused() {
+        assert!(ProfileName::new("exactly sixteen!").is_some());
+        assert!(ProfileName::new("seventeen letters").is_none());
+        assert!(serde_json_core::from_slice::<ProfileName>(b"\"seventeen letters\"").is_err());
+    }
+
+    #[test]
+    fn unsaved_profiles_are_numbered() {
+        let config = Config::default();
+        let names: Vec<_> = (0..PROFILE_COUNT).map(|i| config.profile_name(i)).collect();
+        let names: Vec<_> = names.iter().map(ProfileName::as_str).collect();
+        assert_eq!(names, ["Profile 1", "Profile 2", "Profile 3", "Profile 4"]);
+    }
+
+    #[test]
+    fn apply_narrows_deadzones_to_fit() {
+        let mut config = Config::default();
+        config.calibration.axes[0].center = 100;
+        let mut profile = Profile::capture(ProfileName::numbered(0), &config);
+        profile.deadzones = [500, 40, 0, 0];
+        profile.apply(&mut config);
+        assert_eq!(config.calibration.axes[0].deadzone, 99);
+        assert_eq!(config.calibration.axes[1].deadzone, 40);
+        assert_eq!(config.validate(), Ok(()));
+    }
+
+    #[test]
+    fn combo_fires_once_per_press() {
+        let combo = ProfileCombo {
+            count: 2,
+            buttons: [3, 5, 0],
+        };
+        let mut watch = ComboWatch::new();
+        let mut buttons = [false; BUTTON_COUNT];
+        buttons[3] = true;
+        assert!(!watch.update(&combo, &buttons));
+        buttons[5] = true;
+        assert!(watch.update(&combo, &buttons));
+        assert!(!watch.update(&combo, &buttons));
+        buttons[3] = false;
+        assert!(!watch.update(&combo, &buttons));
+        buttons[3] = true;
+        assert!(watch.update(&combo, &buttons));
+    }
+
+    #[test]
+    fn empty_combo_never_fires() {
+        let mut watch = ComboWatch::new();
+        assert!(!watch.update(&ProfileCombo::new(), &[true; BUTTON_COUNT]));
+    }
+
+    #[test]
+    fn rejects_combo_of_missing_buttons() {
+        let mut combo = ProfileCombo::new();
+        combo.count = MAX_COMBO_BUTTONS as u8 + 1;
+        assert!(!combo.is_valid());
+        combo.count = 1;
+        combo.buttons[0] = BUTTON_COUNT as u8;
+        assert!(!combo.is_valid());
+    }
+}
@@ -0,0 +1,226 @@
+//! Which report button each physical button sends.
+//!
+//! The base layer maps every physical button to a report button. Each shift
+//! layer has a button of its own that, while held, swaps in another mapping
+//! for the rest. A button keeps sending what it was pressed as until it's
+//! released, even if the layer changes while it's held.
+
+use serde::{Deserialize, Serialize};
+
+use crate::inputs::BUTTON_COUNT;
+
+/// Number of shift layers, on top of the base layer.
+pub const SHIFT_LAYERS: usize = 2;
+
+/// In a map, sends nothing. As a shift button, leaves the layer unused.
+pub const NO_BUTTON: u8 = u8::MAX;
+
+/// Every physical button sending the report button of the same index.
+const fn identity() -> [u8; BUTTON_COUNT] {
+    let mut map = [0; BUTTON_COUNT];
+    let mut i = 0;
+    while i < BUTTON_COUNT {
+        map[i] = i as u8;
+        i += 1;
+    }
+    map
+}
+
+fn map_is_valid(map: &[u8; BUTTON_COUNT]) -> bool {
+    map.iter()
+        .all(|&b| b == NO_BUTTON || (b as usize) < BUTTON_COUNT)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShiftLayer {
+    /// The physical button that selects this layer while held. It sends
+    /// nothing itself.
+    pub button: u8,
+    /// The report button each physical button sends on this layer.
+    pub map: [u8; BUTTON_COUNT],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ButtonMap {
+    /// The report button each physical button sends, or [`NO_BUTTON`].
+    pub base: [u8; BUTTON_COUNT],
+    /// If more than one shift button is held, the first layer wins.
+    pub shifts: [ShiftLayer; SHIFT_LAYERS],
+}
+
+impl ButtonMap {
+    /// Every button sends itself, and no shift layers.
+    pub const fn new() -> Self {
+        Self {
+            base: identity(),
+            shifts: [ShiftLayer {
+                button: NO_BUTTON,
+                map: identity(),
+            }; SHIFT_LAYERS],
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        map_is_valid(&self.base)
+            && self.shifts.iter().all(|layer| {
+                (layer.button == NO_BUTTON || (layer.button as usize) < BUTTON_COUNT)
+                    && map_is_valid(&layer.map)
+            })
+    }
+
+    /// The map for `layer`, 0 being the base layer.
+    fn layer(&self, layer: usize) -> &[u8; BUTTON_COUNT] {
+        match layer.checked_sub(1) {
+            Some(shift) => &self.shifts[shift].map,
+            None => &self.base,
+        }
+    }
+
+    /// Whether `button` selects a layer rather than sending anything.
+    fn is_shift(&self, button: usize) -> bool {
+        self.shifts
+            .iter()
+            .any(|layer| layer.button as usize == button)
+    }
+}
+
+impl Default for ButtonMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Remembers which layer each held button was pressed on.
+#[derive(Clone, Copy, Debug)]
+pub struct Remapper {
+    held: [bool; BUTTON_COUNT],
+    layers: [u8; BUTTON_COUNT],
+}
+
+impl Remapper {
+    pub const fn new() -> Self {
+        Self {
+            held: [false; BUTTON_COUNT],
+            layers: [0; BUTTON_COUNT],
+        }
+    }
+
+    /// Maps the physical buttons to the report's.
+    pub fn update(
+        &mut self,
+        map: &ButtonMap,
+        physical: &[bool; BUTTON_COUNT],
+    ) -> [bool; BUTTON_COUNT] {
+        let layer = map
+            .shifts
+            .iter()
+            .position(|shift| physical.get(shift.button as usize) == Some(&true))
+            .map_or(0, |shift| shift + 1);
+
+        let mut report = [false; BUTTON_COUNT];
+        for (button, &pressed) in physical.iter().enumerate() {
+            if pressed && !self.held[button] {
+                self.layers[button] = layer as u8;
+            }
+            self.held[button] = pressed;
+            if !pressed || map.is_shift(button) {
+                continue;
+            }
+            let target = map.layer(self.layers[button] as usize)[button];
+            if let Some(out) = report.get_mut(target as usize) {
+                *out = true;
+            }
+        }
+        report
+    }
+}
+
+impl Default for Remapper {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pressed(buttons: &[usize]) -> [bool; BUTTON_COUNT] {
+        let mut out = [false; BUTTON_COUNT];
+        for &b in buttons {
+            out[b] = true;
+        }
+        out
+    }
+
+    #[test]
+    fn default_map_passes_buttons_through() {
+        let mut remapper = Remapper::new();
+        let buttons = pressed(&[0, 5, 31]);
+        assert_eq!(remapper.update(&ButtonMap::new(), &buttons), buttons);
+    }
+
+    #[test]
+    fn buttons_can_be_moved_merged_and_dropped() {
+        let mut map = ButtonMap::new();
+        map.base[0] = 7;
+        map.base[1] = 7;
+        map.base[2] = NO_BUTTON;
+        let mut remapper = Remapper::new();
+        assert_eq!(remapper.update(&map, &pressed(&[0, 1, 2])), pressed(&[7]));
+        assert_eq!(remapper.update(&map, &pressed(&[1])), pressed(&[7]));
+    }
+
+    #[test]
+    fn shift_swaps_layers_while_held() {
+        let mut map = ButtonMap::new();
+        map.shifts[0].button = 4;
+        map.shifts[0].map[0] = 10;
+        let mut remapper = Remapper::new();
+
+        // The shift button itself sends nothing.
+        assert_eq!(remapper.update(&map, &pressed(&[4])), pressed(&[]));
+        assert_eq!(remapper.update(&map, &pressed(&[4, 0])), pressed(&[10]));
+        assert_eq!(remapper.update(&map, &pressed(&[])), pressed(&[]));
+        assert_eq!(remapper.update(&map, &pressed(&[0])), pressed(&[0]));
+    }
+
+    #[test]
+    fn held_buttons_keep_the_layer_they_were_pressed_on() {
+        let mut map = ButtonMap::new();
+        map.shifts[0].button = 4;
+        map.shifts[0].map[0] = 10;
+        let mut remapper = Remapper::new();
+
+        assert_eq!(remapper.update(&map, &pressed(&[0])), pressed(&[0]));
+        assert_eq!(remapper.update(&map, &pressed(&[0, 4])), pressed(&[0]));
+        assert_eq!(remapper.update(&map, &pressed(&[4])), pressed(&[]));
+        assert_eq!(remapper.update(&map, &pressed(&[4, 0])), pressed(&[10]));
+        assert_eq!(remapper.update(&map, &pressed(&[0])), pressed(&[10]));
+    }
+
+    #[test]
+    fn first_shift_layer_wins() {
+        let mut map = ButtonMap::new();
+        map.shifts[0].button = 4;
+        map.shifts[0].map[0] = 10;
+        map.shifts[1].button = 5;
+        map.shifts[1].map[0] = 20;
+        let mut remapper = Remapper::new();
+        assert_eq!(remapper.update(&map, &pressed(&[5, 0])), pressed(&[20]));
+        assert_eq!(remapper.update(&map, &pressed(&[5])), pressed(&[]));
+        assert_eq!(remapper.update(&map, &pressed(&[4, 5, 0])), pressed(&[10]));
+    }
+
+    #[test]
+    fn rejects_missing_buttons() {
+        let mut map = ButtonMap::new();
+        assert!(map.is_valid());
+        map.base[3] = BUTTON_COUNT as u8;
+        assert!(!map.is_valid());
+
+        let mut map = ButtonMap::new();
+        map.shifts[1].button = BUTTON_COUNT as u8;
+        assert!(!map.is_valid());
+    }
+}
@@ -0,0 +1,70 @@
+use core::cell::Cell;
+
+use embassy_futures::select::{select, Either};
+use embassy_rp::{
+    interrupt::typelevel::{Binding, PIO1_IRQ_0},
+    peripherals::PIO1,
+    pio::{InterruptHandler, Pio, PioPin},
+    pio_programs::rotary_encoder::{Direction, PioEncoder, PioEncoderProgram},
+    Peripheral,
+};
+use embassy_sync::blocking_mutex::{raw::CriticalSectionRawMutex, Mutex};
+use usb_joystick::encoders::ENCODER_COUNT;
+
+/// Steps counted since the joystick task last took them, clockwise positive.
+static STEPS: Mutex<CriticalSectionRawMutex, Cell<[i32; ENCODER_COUNT]>> =
+    Mutex::new(Cell::new([0; ENCODER_COUNT]));
+
+/// Takes the steps each encoder has made since the last call.
+pub fn take_steps() -> [i32; ENCODER_COUNT] {
+    STEPS.lock(|steps| steps.replace([0; ENCODER_COUNT]))
+}
+
+/// Counts the encoders with PIO1, one state machine each, so no step is
+/// missed however busy the CPU is.
+pub struct EncoderRunner {
+    encoders: (PioEncoder<'static, PIO1, 0>, PioEncoder<'static, PIO1, 1>),
+}
+
+impl EncoderRunner {
+    pub async fn run(&mut self) -> ! {
+        let (first, second) = &mut self.encoders;
+        loop {
+            let (index, direction) = match select(first.read(), second.read()).await {
+                Either::First(direction) => (0, direction),
+                Either::Second(direction) => (1, direction),
+            };
+            let step = match direction {
+                Direction::Clockwise => 1,
+                Direction::CounterClockwise => -1,
+            };
+            STEPS.lock(|steps| {
+                let mut counts = steps.get();
+                counts[index] += step;
+                steps.set(counts);
+            });
+        }
+    }
+}
+
+/// Each encoder's A and B pins, which are pulled up, with the common pin
+/// to ground.
+pub fn make_encoders(
+    pio: impl Peripheral<P = PIO1> + 'static,
+    irqs: impl Binding<PIO1_IRQ_0, InterruptHandler<PIO1>>,
+    pins_0: (impl PioPin, impl PioPin),
+    pins_1: (impl PioPin, impl PioPin),
+) -> EncoderRunner {
+    let Pio {
+        mut common,
+        sm0,
+        sm1,
+        ..
+    } = Pio::new(pio, irqs);
+    let program = PioEncoderProgram::new(&mut common);
+    let encoders = (
+        PioEncoder::new(&mut common, sm0, pins_0.0, pins_0.1, &program),
+        PioEncoder::new(&mut common, sm1, pins_1.0, pins_1.1, &program),
+    );
+    EncoderRunner { encoders }
+}
@@ -0,0 +1,78 @@
+use core::cell::Cell;
+
+use defmt::warn;
+use embassy_rp::{i2c::I2c, peripherals::I2C1};
+use embassy_sync::{
+    blocking_mutex::{self, raw::CriticalSectionRawMutex},
+    mutex::Mutex,
+};
+use embassy_time::{Delay, Timer};
+use usb_joystick::sensors::{self, SensorError, SensorKind, SENSOR_CHANNELS};
+
+use crate::state::SharedState;
+
+/// Time between reads of the sensor, in milliseconds.
+const POLL_MS: u64 = 1;
+
+/// Time to wait before trying again after the sensor fails to answer, or
+/// before checking whether one has been configured, in milliseconds.
+const RETRY_MS: u64 = 500;
+
+/// The latest readings, `None` where there is no sensor or it isn't
+/// answering.
+static READINGS: blocking_mutex::Mutex<
+    CriticalSectionRawMutex,
+    Cell<[Option<u16>; SENSOR_CHANNELS]>,
+> = blocking_mutex::Mutex::new(Cell::new([None; SENSOR_CHANNELS]));
+
+pub fn latest() -> [Option<u16>; SENSOR_CHANNELS] {
+    READINGS.lock(Cell::get)
+}
+
+/// Reads the sensor on I2C1 over and over, so the joystick task never has
+/// to wait on the bus.
+pub struct SensorRunner {
+    i2c: I2c<'static, I2C1, embassy_rp::i2c::Async>,
+    state: &'static Mutex<CriticalSectionRawMutex, SharedState>,
+}
+
+impl SensorRunner {
+    pub fn new(
+        i2c: I2c<'static, I2C1, embassy_rp::i2c::Async>,
+        state: &'static Mutex<CriticalSectionRawMutex, SharedState>,
+    ) -> Self {
+        Self { i2c, state }
+    }
+
+    pub async fn run(&mut self) -> ! {
+        loop {
+            let config = self.state.lock().await.config.sensor;
+            if config.kind == SensorKind::None {
+                READINGS.lock(|readings| readings.set([None; SENSOR_CHANNELS]));
+                Timer::after_millis(RETRY_MS).await;
+                continue;
+            }
+            match sensors::read(&mut self.i2c, &mut Delay, &config).await {
+                Ok(latest) => {
+                    READINGS.lock(|readings| readings.set(latest));
+                    Timer::after_millis(POLL_MS).await;
+                }
+                Err(SensorError::Bus(e)) => {
+                    warn!(
+                        "Failed to read sensor at {=u8:#x}: {:?}",
+                        config.address(),
+                        e
+                    );
+                    READINGS.lock(|readings| readings.set([None; SENSOR_CHANNELS]));
+                    Timer::after_millis(RETRY_MS).await;
+                }
+                // The sensor is there but was slow this once, so the last
+                // readings stand until the next poll.
+                Err(SensorError::Timeout) => {
+                    warn!("Sensor at {=u8:#x} timed out", config.address());
+                    Timer::after_millis(POLL_MS).await;
+                }
+            }
+        }
+    }
+}
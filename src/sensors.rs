@@ -0,0 +1,378 @@
+//! External sensors on the I2C bus, read as extra axes.
+//!
+//! Every sensor's readings are scaled to the 12-bit range the RP2040's own
+//! ADC gives, so they go through the same calibration as the onboard axes.
+
+use embedded_hal_async::{delay::DelayNs, i2c::I2c};
+use serde::{Deserialize, Serialize};
+
+use crate::axis::ADC_MAX;
+
+/// Most readings one sensor gives.
+pub const SENSOR_CHANNELS: usize = 4;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SensorKind {
+    #[default]
+    None,
+    /// ADS1115 16-bit ADC, reading its four inputs against ground.
+    Ads1115,
+    /// AS5600 hall-effect angle sensor, one reading.
+    As5600,
+}
+
+impl SensorKind {
+    /// The address the part answers on with its address pins left alone.
+    pub const fn default_address(&self) -> u8 {
+        match self {
+            SensorKind::None => 0,
+            SensorKind::Ads1115 => ads1115::ADDRESS,
+            SensorKind::As5600 => as5600::ADDRESS,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SensorConfig {
+    pub kind: SensorKind,
+    /// 7-bit I2C address, or 0 for the part's default.
+    pub address: u8,
+}
+
+impl SensorConfig {
+    pub const fn new() -> Self {
+        Self {
+            kind: SensorKind::None,
+            address: 0,
+        }
+    }
+
+    pub fn address(&self) -> u8 {
+        match self.address {
+            0 => self.kind.default_address(),
+            address => address,
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        // Addresses outside this range are reserved by the I2C spec.
+        self.kind == SensorKind::None || (0x08..0x78).contains(&self.address())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SensorError<E> {
+    /// The bus failed, or nothing answered.
+    Bus(E),
+    /// The sensor never said its reading was ready.
+    Timeout,
+}
+
+/// Reads every channel of the sensor `config` describes. Channels the
+/// sensor doesn't have read as `None`.
+pub async fn read<I: I2c, D: DelayNs>(
+    i2c: &mut I,
+    delay: &mut D,
+    config: &SensorConfig,
+) -> Result<[Option<u16>; SENSOR_CHANNELS], SensorError<I::Error>> {
+    let mut readings = [None; SENSOR_CHANNELS];
+    match config.kind {
+        SensorKind::None => {}
+        SensorKind::Ads1115 => {
+            for (channel, reading) in readings.iter_mut().enumerate() {
+                *reading = Some(ads1115::read(i2c, delay, config.address(), channel as u8).await?);
+            }
+        }
+        SensorKind::As5600 => {
+            let angle = as5600::read(i2c, config.address()).await;
+            readings[0] = Some(angle.map_err(SensorError::Bus)?);
+        }
+    }
+    Ok(readings)
+}
+
+pub mod ads1115 {
+    //! Single-shot conversions on the ADS1115.
+
+    use super::*;
+
+    pub const ADDRESS: u8 = 0x48;
+
+    const CONVERSION: u8 = 0x00;
+    const CONFIG: u8 = 0x01;
+
+    /// Starts a conversion, or reads back as set once it's done.
+    const OS: u16 = 1 << 15;
+    /// Input against ground; add the channel.
+    const MUX_SINGLE: u16 = 0b100 << 12;
+    /// ±4.096 V full scale, enough to cover a 3.3 V supply.
+    const PGA_4V096: u16 = 0b001 << 9;
+    const MODE_SINGLE_SHOT: u16 = 1 << 8;
+    const DR_860SPS: u16 = 0b111 << 5;
+    const COMP_DISABLE: u16 = 0b11;
+
+    /// The supply the ADS1115 and whatever drives its inputs run from, in
+    /// millivolts. Readings are scaled so that this is full scale.
+    pub const SUPPLY_MV: u32 = 3300;
+
+    /// Counts per millivolt at ±4.096 V.
+    const COUNTS_PER_MV: u32 = 8;
+
+    /// Time a conversion takes at 860 samples per second, in microseconds.
+    const CONVERSION_US: u32 = 1200;
+
+    /// Times to check a conversion has finished before giving up on it.
+    const READY_TRIES: usize = 4;
+
+    fn config_word(channel: u8) -> u16 {
+        OS | MUX_SINGLE
+            | (channel as u16 & 0b11) << 12
+            | PGA_4V096
+            | MODE_SINGLE_SHOT
+            | DR_860SPS
+            | COMP_DISABLE
+    }
+
+    /// Converts `channel` and returns it scaled to 12 bits, from 0 at ground
+    /// to 4095 at [`SUPPLY_MV`], so a sensor powered from the same 3.3 V
+    /// uses the whole range. Inputs above that read 4095. Gives up with
+    /// [`SensorError::Timeout`] rather than return a conversion that hasn't
+    /// finished.
+    pub async fn read<I: I2c, D: DelayNs>(
+        i2c: &mut I,
+        delay: &mut D,
+        address: u8,
+        channel: u8,
+    ) -> Result<u16, SensorError<I::Error>> {
+        let [hi, lo] = config_word(channel).to_be_bytes();
+        i2c.write(address, &[CONFIG, hi, lo])
+            .await
+            .map_err(SensorError::Bus)?;
+        delay.delay_us(CONVERSION_US).await;
+        let mut ready = false;
+        for _ in 0..READY_TRIES {
+            let mut config = [0; 2];
+            i2c.write_read(address, &[CONFIG], &mut config)
+                .await
+                .map_err(SensorError::Bus)?;
+            ready = u16::from_be_bytes(config) & OS != 0;
+            if ready {
+                break;
+            }
+            delay.delay_us(CONVERSION_US / 4).await;
+        }
+        if !ready {
+            return Err(SensorError::Timeout);
+        }
+        let mut value = [0; 2];
+        i2c.write_read(address, &[CONVERSION], &mut value)
+            .await
+            .map_err(SensorError::Bus)?;
+        // Inputs a little below ground read negative.
+        let value = i16::from_be_bytes(value).max(0) as u32;
+        let full_scale = SUPPLY_MV * COUNTS_PER_MV;
+        Ok((value * ADC_MAX as u32 / full_scale).min(ADC_MAX as u32) as u16)
+    }
+}
+
+pub mod as5600 {
+    //! The AS5600's raw angle.
+
+    use super::*;
+
+    pub const ADDRESS: u8 = 0x36;
+
+    const RAW_ANGLE: u8 = 0x0c;
+
+    /// Returns the angle, 0 to 4095 for a full turn.
+    pub async fn read<I: I2c>(i2c: &mut I, address: u8) -> Result<u16, I::Error> {
+        let mut angle = [0; 2];
+        i2c.write_read(address, &[RAW_ANGLE], &mut angle).await?;
+        Ok(u16::from_be_bytes(angle) & ADC_MAX)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embassy_futures::block_on;
+    use embedded_hal_async::i2c::{ErrorKind, ErrorType, Operation, SevenBitAddress};
+
+    /// One register-addressed device: writes of a register and data are
+    /// logged, and reads come back from `registers`.
+    #[derive(Default)]
+    struct MockBus {
+        address: u8,
+        registers: std::collections::HashMap<u8, [u8; 2]>,
+        /// Set the OS bit once the config register has been read this many
+        /// times after a write.
+        busy_reads: usize,
+        writes: Vec<Vec<u8>>,
+        fail: bool,
+    }
+
+    impl ErrorType for MockBus {
+        type Error = ErrorKind;
+    }
+
+    impl I2c<SevenBitAddress> for MockBus {
+        async fn transaction(
+            &mut self,
+            address: u8,
+            operations: &mut [Operation<'_>],
+        ) -> Result<(), ErrorKind> {
+            if self.fail || address != self.address {
+                return Err(ErrorKind::NoAcknowledge(
+                    embedded_hal_async::i2c::NoAcknowledgeSource::Address,
+                ));
+            }
+            let mut register = None;
+            for operation in operations {
+                match operation {
+                    Operation::Write(bytes) => {
+                        register = bytes.first().copied();
+                        if bytes.len() > 1 {
+                            self.writes.push(bytes.to_vec());
+                        }
+                    }
+                    Operation::Read(buf) => {
+                        let register = register.expect("read without a register");
+                        let mut value = self.registers.get(&register).copied().unwrap_or_default();
+                        if register == 0x01 {
+                            if self.busy_reads > 0 {
+                                self.busy_reads -= 1;
+                                value[0] &= 0x7f;
+                            } else {
+                                value[0] |= 0x80;
+                            }
+                        }
+                        buf.copy_from_slice(&value[..buf.len()]);
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    struct NoDelay;
+
+    impl DelayNs for NoDelay {
+        async fn delay_ns(&mut self, _ns: u32) {}
+    }
+
+    #[test]
+    fn ads1115_starts_a_conversion_per_channel() {
+        let mut bus = MockBus {
+            address: 0x48,
+            ..Default::default()
+        };
+        // Half the supply.
+        bus.registers.insert(0x00, 13200u16.to_be_bytes());
+        let config = SensorConfig {
+            kind: SensorKind::Ads1115,
+            address: 0x48,
+        };
+        let readings = block_on(read(&mut bus, &mut NoDelay, &config)).unwrap();
+        assert_eq!(readings, [Some(ADC_MAX / 2); 4]);
+        assert_eq!(
+            bus.writes,
+            [
+                vec![0x01, 0xc3, 0xe3],
+                vec![0x01, 0xd3, 0xe3],
+                vec![0x01, 0xe3, 0xe3],
+                vec![0x01, 0xf3, 0xe3],
+            ]
+        );
+    }
+
+    #[test]
+    fn ads1115_waits_for_the_conversion() {
+        let mut bus = MockBus {
+            address: 0x48,
+            busy_reads: 2,
+            ..Default::default()
+        };
+        bus.registers.insert(0x00, 0x7fffu16.to_be_bytes());
+        let value = block_on(ads1115::read(&mut bus, &mut NoDelay, 0x48, 1)).unwrap();
+        assert_eq!(value, ADC_MAX);
+        assert_eq!(bus.busy_reads, 0);
+    }
+
+    #[test]
+    fn ads1115_gives_up_on_a_conversion_that_never_finishes() {
+        let mut bus = MockBus {
+            address: 0x48,
+            busy_reads: usize::MAX,
+            ..Default::default()
+        };
+        bus.registers.insert(0x00, 0x4000u16.to_be_bytes());
+        let result = block_on(ads1115::read(&mut bus, &mut NoDelay, 0x48, 0));
+        assert_eq!(result, Err(SensorError::Timeout));
+    }
+
+    #[test]
+    fn ads1115_reads_the_supply_as_full_scale() {
+        let mut bus = MockBus {
+            address: 0x48,
+            ..Default::default()
+        };
+        let supply = (ads1115::SUPPLY_MV * 8) as u16;
+        bus.registers.insert(0x00, supply.to_be_bytes());
+        let value = block_on(ads1115::read(&mut bus, &mut NoDelay, 0x48, 0)).unwrap();
+        assert_eq!(value, ADC_MAX);
+    }
+
+    #[test]
+    fn ads1115_clamps_below_ground() {
+        let mut bus = MockBus {
+            address: 0x48,
+            ..Default::default()
+        };
+        bus.registers.insert(0x00, (-20i16).to_be_bytes());
+        let value = block_on(ads1115::read(&mut bus, &mut NoDelay, 0x48, 0)).unwrap();
+        assert_eq!(value, 0);
+    }
+
+    #[test]
+    fn as5600_reads_the_raw_angle() {
+        let mut bus = MockBus {
+            address: 0x36,
+            ..Default::default()
+        };
+        bus.registers.insert(0x0c, [0xf1, 0x23]);
+        let config = SensorConfig {
+            kind: SensorKind::As5600,
+            address: 0,
+        };
+        let readings = block_on(read(&mut bus, &mut NoDelay, &config)).unwrap();
+        assert_eq!(readings, [Some(0x123), None, None, None]);
+    }
+
+    #[test]
+    fn bus_errors_are_passed_on() {
+        let mut bus = MockBus {
+            address: 0x36,
+            fail: true,
+            ..Default::default()
+        };
+        let config = SensorConfig {
+            kind: SensorKind::As5600,
+            address: 0x36,
+        };
+        assert!(block_on(read(&mut bus, &mut NoDelay, &config)).is_err());
+    }
+
+    #[test]
+    fn rejects_reserved_addresses() {
+        let mut config = SensorConfig {
+            kind: SensorKind::Ads1115,
+            address: 0x48,
+        };
+        assert!(config.is_valid());
+        config.address = 0x78;
+        assert!(!config.is_valid());
+        config.address = 0;
+        assert!(config.is_valid());
+        assert!(SensorConfig::new().is_valid());
+    }
+}
@@ -0,0 +1,102 @@
+//! Where each axis in the report takes its raw sample from.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    encoders::ENCODER_COUNT, sensors::SENSOR_CHANNELS, virtual_controls::BUTTON_AXIS_COUNT,
+};
+
+/// ADC inputs on GPIO 26 to 29, not counting the temperature sensor.
+pub const ADC_CHANNELS: usize = 4;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AxisSource {
+    /// Left out of the report altogether.
+    #[default]
+    None,
+    /// One of the ADC inputs, 0 being GPIO 26.
+    Adc(u8),
+    /// The RP2040's own temperature sensor.
+    Temperature,
+    /// A reading from the sensor on the I2C bus.
+    Sensor(u8),
+    /// The position an encoder has been turned to.
+    Encoder(u8),
+    /// One of the axes driven by a pair of buttons.
+    Buttons(u8),
+}
+
+impl AxisSource {
+    pub fn is_valid(&self) -> bool {
+        match *self {
+            AxisSource::None | AxisSource::Temperature => true,
+            AxisSource::Adc(channel) => (channel as usize) < ADC_CHANNELS,
+            AxisSource::Sensor(channel) => (channel as usize) < SENSOR_CHANNELS,
+            AxisSource::Encoder(encoder) => (encoder as usize) < ENCODER_COUNT,
+            AxisSource::Buttons(axis) => (axis as usize) < BUTTON_AXIS_COUNT,
+        }
+    }
+
+    /// This source's sample, in the 12-bit range of the ADC. `None` if the
+    /// axis has no source, or its sensor isn't answering.
+    pub fn sample(&self, samples: &Samples) -> Option<u16> {
+        match *self {
+            AxisSource::None => None,
+            AxisSource::Adc(channel) => samples.adc.get(channel as usize).copied(),
+            AxisSource::Temperature => Some(samples.temperature),
+            AxisSource::Sensor(channel) => samples.sensor.get(channel as usize).copied().flatten(),
+            AxisSource::Encoder(encoder) => samples.encoders.get(encoder as usize).copied(),
+            AxisSource::Buttons(axis) => samples.button_axes.get(axis as usize).copied(),
+        }
+    }
+}
+
+/// Everything an axis can be read from, as sampled in one poll.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Samples {
+    pub adc: [u16; ADC_CHANNELS],
+    pub temperature: u16,
+    pub sensor: [Option<u16>; SENSOR_CHANNELS],
+    pub encoders: [u16; ENCODER_COUNT],
+    pub button_axes: [u16; BUTTON_AXIS_COUNT],
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples() -> Samples {
+        Samples {
+            adc: [10, 11, 12, 13],
+            temperature: 876,
+            sensor: [Some(20), None, None, None],
+            encoders: [30, 31],
+            button_axes: [40, 41],
+        }
+    }
+
+    #[test]
+    fn each_source_reads_its_sample() {
+        let samples = samples();
+        assert_eq!(AxisSource::None.sample(&samples), None);
+        assert_eq!(AxisSource::Adc(3).sample(&samples), Some(13));
+        assert_eq!(AxisSource::Temperature.sample(&samples), Some(876));
+        assert_eq!(AxisSource::Sensor(0).sample(&samples), Some(20));
+        assert_eq!(AxisSource::Encoder(1).sample(&samples), Some(31));
+        assert_eq!(AxisSource::Buttons(0).sample(&samples), Some(40));
+    }
+
+    #[test]
+    fn silent_sensor_reads_nothing() {
+        assert_eq!(AxisSource::Sensor(1).sample(&samples()), None);
+    }
+
+    #[test]
+    fn rejects_missing_inputs() {
+        assert!(AxisSource::Adc(3).is_valid());
+        assert!(!AxisSource::Adc(4).is_valid());
+        assert!(!AxisSource::Sensor(SENSOR_CHANNELS as u8).is_valid());
+        assert!(!AxisSource::Encoder(ENCODER_COUNT as u8).is_valid());
+        assert!(!AxisSource::Buttons(BUTTON_AXIS_COUNT as u8).is_valid());
+    }
+}
@@ -1,7 +1,26 @@
-use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, mutex::Mutex};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, mutex::Mutex, watch::Watch};
+use usb_joystick::{
+    calibration::Capture, config::Config, hid_descriptor::ReportLayout, idle::IdleRate,
+    inputs::Inputs,
+};
+
+/// How many browsers can stream inputs at the same time.
+pub const MAX_INPUT_STREAMS: usize = 2;
+
+/// Every [`Inputs`] the joystick task produces, for streaming to browsers.
+pub static INPUT_UPDATES: Watch<CriticalSectionRawMutex, Inputs, MAX_INPUT_STREAMS> = Watch::new();
 
 pub struct SharedState {
-    pub power: bool,
+    pub config: Config,
+    /// The axes in the report, fixed by the descriptor the host read at
+    /// startup.
+    pub layout: ReportLayout,
+    /// Set while a calibration capture is running.
+    pub capture: Option<Capture>,
+    /// Published by the joystick task after every report.
+    pub inputs: Inputs,
+    /// Set by the host with SET_IDLE.
+    pub idle: IdleRate,
 }
 
 #[derive(Clone, Copy)]
@@ -0,0 +1,85 @@
+use defmt::{info, warn, Debug2Format};
+use embassy_rp::{
+    flash::{Blocking, Flash},
+    peripherals::FLASH,
+};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, mutex::Mutex, signal::Signal};
+use embassy_time::Timer;
+use usb_joystick::{config::Config, config_store::ConfigStore, led_patterns::Pattern};
+
+use crate::{
+    leds::{self, LedCommand},
+    state::SharedState,
+};
+
+const FLASH_SIZE: usize = 2 * 1024 * 1024;
+
+/// How long to wait after a change before writing it out, so that a burst of
+/// changes costs a single write.
+const SAVE_DELAY_MS: u64 = 2000;
+
+/// Flashed on the status LEDs when a save fails.
+const SAVE_FAILED_CODE: u8 = 1;
+
+pub type ConfigFlash = Flash<'static, FLASH, Blocking, FLASH_SIZE>;
+
+static SAVE_REQUESTED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+extern "C" {
+    // Defined in memory.x
+    static __config_start: u32;
+    static __config_end: u32;
+}
+
+/// Asks the storage task to write the current config to flash.
+pub fn request_save() {
+    SAVE_REQUESTED.signal(());
+}
+
+pub fn make_config_store(flash: FLASH) -> ConfigStore<ConfigFlash> {
+    // Only the addresses of the linker symbols are used, they are never read.
+    let start = &raw const __config_start as u32;
+    let end = &raw const __config_end as u32;
+    ConfigStore::new(Flash::new_blocking(flash), start, end)
+}
+
+pub fn load_config(store: &mut ConfigStore<ConfigFlash>) -> Config {
+    match store.load() {
+        Some(config) => {
+            info!("Loaded config from flash");
+            config
+        }
+        None => {
+            warn!("No valid config in flash, using defaults");
+            Config::default()
+        }
+    }
+}
+
+#[embassy_executor::task]
+pub async fn storage_task(
+    mut store: ConfigStore<ConfigFlash>,
+    state: &'static Mutex<CriticalSectionRawMutex, SharedState>,
+    mut saved: Config,
+) -> ! {
+    loop {
+        SAVE_REQUESTED.wait().await;
+        Timer::after_millis(SAVE_DELAY_MS).await;
+        SAVE_REQUESTED.reset();
+
+        let config = state.lock().await.config;
+        if config == saved {
+            continue;
+        }
+        match store.save(&config) {
+            Ok(()) => {
+                info!("Saved config to flash");
+                saved = config;
+            }
+            Err(e) => {
+                warn!("Failed to save config: {:?}", Debug2Format(&e));
+                leds::send(LedCommand::Pattern(Pattern::ErrorCode(SAVE_FAILED_CODE)));
+            }
+        }
+    }
+}
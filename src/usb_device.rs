@@ -41,5 +41,5 @@ where
         builder
     };
 
-    return builder;
+    builder
 }
@@ -0,0 +1,403 @@
+//! Controls that exist only in the report: buttons pressed by an axis
+//! crossing a threshold, and axes driven by a pair of buttons.
+//!
+//! Axis buttons work on report axes and buttons, after calibration, curves,
+//! remapping and button modes. Button axes are a source like any other, so an
+//! axis takes one up by naming it in
+//! [`Config::axis_sources`](crate::config::Config::axis_sources).
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    axis::{ADC_MAX, AXIS_COUNT, AXIS_MAX, AXIS_MIN},
+    inputs::BUTTON_COUNT,
+    remap::NO_BUTTON,
+};
+
+/// Buttons that can be driven by axes, enough for a throttle's detents.
+pub const AXIS_BUTTON_COUNT: usize = 4;
+
+/// Axes that can be driven by buttons.
+pub const BUTTON_AXIS_COUNT: usize = 2;
+
+/// Where a button axis starts, and springs back to, as an ADC sample would
+/// read.
+const CENTER: u16 = ADC_MAX.div_ceil(2);
+
+/// Fraction of an ADC step a button axis keeps track of, so that slow slews
+/// still move a little every poll.
+const SUBSTEPS: u32 = 1000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AxisButton {
+    /// The report button to press, or [`NO_BUTTON`] to leave this unused.
+    pub button: u8,
+    pub axis: u8,
+    /// Pressed once the axis reaches this, in the report's range.
+    pub threshold: i16,
+    /// Pressed at or below the threshold, rather than at or above it.
+    pub below: bool,
+    /// How far back past the threshold the axis must go before the button is
+    /// released, so that a noisy axis resting on it doesn't chatter.
+    pub hysteresis: u16,
+}
+
+impl AxisButton {
+    pub const fn new() -> Self {
+        Self {
+            button: NO_BUTTON,
+            axis: 0,
+            threshold: AXIS_MAX / 2,
+            below: false,
+            hysteresis: 1000,
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        (self.button == NO_BUTTON || (self.button as usize) < BUTTON_COUNT)
+            && (self.axis as usize) < AXIS_COUNT
+            && (AXIS_MIN..=AXIS_MAX).contains(&self.threshold)
+    }
+}
+
+impl Default for AxisButton {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Remembers which axis buttons are pressed, for their hysteresis.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AxisButtons {
+    pressed: [bool; AXIS_BUTTON_COUNT],
+}
+
+impl AxisButtons {
+    pub const fn new() -> Self {
+        Self {
+            pressed: [false; AXIS_BUTTON_COUNT],
+        }
+    }
+
+    /// Presses the buttons whose axes are past their thresholds. Buttons
+    /// already held stay held.
+    pub fn update(
+        &mut self,
+        config: &[AxisButton; AXIS_BUTTON_COUNT],
+        axes: &[i16; AXIS_COUNT],
+        buttons: &mut [bool; BUTTON_COUNT],
+    ) {
+        for (pressed, config) in self.pressed.iter_mut().zip(config) {
+            let Some(value) = axes.get(config.axis as usize) else {
+                *pressed = false;
+                continue;
+            };
+            // How far past the threshold the axis is, in the direction that
+            // presses the button.
+            let past = if config.below {
+                config.threshold as i32 - *value as i32
+            } else {
+                *value as i32 - config.threshold as i32
+            };
+            let release_at = if *pressed {
+                config.hysteresis as i32
+            } else {
+                0
+            };
+            *pressed = past >= -release_at;
+            if let Some(button) = buttons.get_mut(config.button as usize) {
+                *button |= *pressed;
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ButtonAxis {
+    /// Indices of the buttons that move the axis down and up. They are taken
+    /// out of the button field while an axis uses this.
+    pub buttons: [u8; 2],
+    /// How long the axis takes to go from one end to the other, in
+    /// milliseconds. 0 jumps straight there.
+    pub travel_ms: u16,
+    /// Whether the axis springs back to the centre when neither button is
+    /// held, rather than staying where it was left.
+    pub centering: bool,
+}
+
+impl ButtonAxis {
+    pub const fn new() -> Self {
+        Self {
+            buttons: [28, 29],
+            travel_ms: 1000,
+            centering: true,
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.buttons.iter().all(|&b| (b as usize) < BUTTON_COUNT)
+    }
+
+    /// Where the axis is heading with `buttons` held, if anywhere. Both
+    /// buttons held cancel out.
+    fn target(&self, buttons: &[bool; BUTTON_COUNT]) -> Option<u16> {
+        let [down, up] = self.buttons.map(|b| buttons[b as usize]);
+        match (down, up) {
+            (true, false) => Some(0),
+            (false, true) => Some(ADC_MAX),
+            _ => self.centering.then_some(CENTER),
+        }
+    }
+}
+
+impl Default for ButtonAxis {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Moves each button axis towards where its buttons send it, no faster than
+/// its slew rate.
+#[derive(Clone, Copy, Debug)]
+pub struct ButtonAxes {
+    /// Where each axis is, in [`SUBSTEPS`] of an ADC step.
+    positions: [u32; BUTTON_AXIS_COUNT],
+    last_ms: u64,
+}
+
+impl ButtonAxes {
+    pub const fn new() -> Self {
+        Self {
+            positions: [CENTER as u32 * SUBSTEPS; BUTTON_AXIS_COUNT],
+            last_ms: 0,
+        }
+    }
+
+    /// Where each button axis is, as an ADC sample would read, for button
+    /// axes that are the source of an axis.
+    pub fn positions(&self) -> [u16; BUTTON_AXIS_COUNT] {
+        self.positions
+            .map(|position| ((position + SUBSTEPS / 2) / SUBSTEPS) as u16)
+    }
+
+    /// Moves the axes in `used` along, and releases the buttons they take so
+    /// they only show up on the axis.
+    pub fn update(
+        &mut self,
+        config: &[ButtonAxis; BUTTON_AXIS_COUNT],
+        used: &[bool; BUTTON_AXIS_COUNT],
+        now_ms: u64,
+        buttons: &mut [bool; BUTTON_COUNT],
+    ) {
+        let elapsed = now_ms.saturating_sub(self.last_ms);
+        self.last_ms = now_ms;
+
+        for ((position, config), _) in self
+            .positions
+            .iter_mut()
+            .zip(config)
+            .zip(used)
+            .filter(|(_, &used)| used)
+        {
+            let Some(target) = config.target(buttons) else {
+                continue;
+            };
+            let target = target as u32 * SUBSTEPS;
+            let step = match config.travel_ms {
+                0 => u32::MAX,
+                travel_ms => {
+                    let step = ADC_MAX as u64 * SUBSTEPS as u64 * elapsed / travel_ms as u64;
+                    step.min(u32::MAX as u64) as u32
+                }
+            };
+            *position = if target > *position {
+                position.saturating_add(step).min(target)
+            } else {
+                position.saturating_sub(step).max(target)
+            };
+        }
+
+        for (config, _) in config.iter().zip(used).filter(|(_, &used)| used) {
+            for b in config.buttons {
+                buttons[b as usize] = false;
+            }
+        }
+    }
+}
+
+impl Default for ButtonAxes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn watch(axis: u8, threshold: i16, below: bool, button: u8) -> [AxisButton; AXIS_BUTTON_COUNT] {
+        let mut config = [AxisButton::new(); AXIS_BUTTON_COUNT];
+        config[0] = AxisButton {
+            button,
+            axis,
+            threshold,
+            below,
+            hysteresis: 1000,
+        };
+        config
+    }
+
+    fn axis_buttons(
+        state: &mut AxisButtons,
+        config: &[AxisButton; AXIS_BUTTON_COUNT],
+        axis: usize,
+        value: i16,
+    ) -> [bool; BUTTON_COUNT] {
+        let mut axes = [0; AXIS_COUNT];
+        axes[axis] = value;
+        let mut buttons = [false; BUTTON_COUNT];
+        state.update(config, &axes, &mut buttons);
+        buttons
+    }
+
+    #[test]
+    fn axis_presses_its_button_past_the_threshold() {
+        let config = watch(2, 20000, false, 7);
+        let mut state = AxisButtons::new();
+        assert!(!axis_buttons(&mut state, &config, 2, 19999)[7]);
+        assert!(axis_buttons(&mut state, &config, 2, 20000)[7]);
+        assert!(axis_buttons(&mut state, &config, 2, AXIS_MAX)[7]);
+        // Another axis crossing the same value doesn't count.
+        let mut state = AxisButtons::new();
+        assert!(!axis_buttons(&mut state, &config, 1, AXIS_MAX)[7]);
+    }
+
+    #[test]
+    fn axis_button_can_watch_below_the_threshold() {
+        let config = watch(0, -20000, true, 3);
+        let mut state = AxisButtons::new();
+        assert!(!axis_buttons(&mut state, &config, 0, 0)[3]);
+        assert!(axis_buttons(&mut state, &config, 0, -25000)[3]);
+    }
+
+    #[test]
+    fn hysteresis_holds_the_button_near_the_threshold() {
+        let config = watch(0, 20000, false, 3);
+        let mut state = AxisButtons::new();
+        assert!(axis_buttons(&mut state, &config, 0, 20000)[3]);
+        assert!(axis_buttons(&mut state, &config, 0, 19000)[3]);
+        assert!(!axis_buttons(&mut state, &config, 0, 18999)[3]);
+        // And it takes the threshold itself to press it again.
+        assert!(!axis_buttons(&mut state, &config, 0, 19500)[3]);
+    }
+
+    #[test]
+    fn axis_buttons_only_add_presses() {
+        let config = watch(0, 20000, false, 3);
+        let mut state = AxisButtons::new();
+        let mut buttons = [false; BUTTON_COUNT];
+        buttons[3] = true;
+        state.update(&config, &[0; AXIS_COUNT], &mut buttons);
+        assert!(buttons[3]);
+        // Unused entries press nothing.
+        let mut buttons = [false; BUTTON_COUNT];
+        state.update(&config, &[AXIS_MAX; AXIS_COUNT], &mut buttons);
+        assert_eq!(buttons.iter().filter(|&&b| b).count(), 1);
+    }
+
+    fn held(down: bool, up: bool) -> [bool; BUTTON_COUNT] {
+        let mut buttons = [false; BUTTON_COUNT];
+        buttons[28] = down;
+        buttons[29] = up;
+        buttons
+    }
+
+    fn slew(
+        state: &mut ButtonAxes,
+        config: &ButtonAxis,
+        now_ms: u64,
+        buttons: [bool; BUTTON_COUNT],
+    ) -> u16 {
+        let config = [*config; BUTTON_AXIS_COUNT];
+        let mut buttons = buttons;
+        state.update(&config, &[true, false], now_ms, &mut buttons);
+        state.positions()[0]
+    }
+
+    #[test]
+    fn button_axis_slews_at_its_rate() {
+        let config = ButtonAxis::new();
+        let mut state = ButtonAxes::new();
+        assert_eq!(slew(&mut state, &config, 0, held(false, false)), CENTER);
+        // A second for the whole range, so a quarter second for a quarter.
+        let quarter = slew(&mut state, &config, 250, held(false, true));
+        assert!(quarter.abs_diff(CENTER + ADC_MAX / 4) <= 1, "{quarter}");
+        assert_eq!(slew(&mut state, &config, 1000, held(false, true)), ADC_MAX);
+        assert_eq!(slew(&mut state, &config, 3000, held(true, false)), 0);
+    }
+
+    #[test]
+    fn button_axis_moves_a_little_every_millisecond() {
+        let config = ButtonAxis {
+            travel_ms: u16::MAX,
+            ..ButtonAxis::new()
+        };
+        let mut state = ButtonAxes::new();
+        let mut last = slew(&mut state, &config, 0, held(false, true));
+        let mut moved = 0;
+        for now in 1..=100 {
+            let position = slew(&mut state, &config, now, held(false, true));
+            moved += (position > last) as u32;
+            last = position;
+        }
+        assert!(moved >= 5, "{moved}");
+        assert!(last > CENTER);
+    }
+
+    #[test]
+    fn button_axis_centres_or_stays_put() {
+        let mut config = ButtonAxis {
+            travel_ms: 0,
+            ..ButtonAxis::new()
+        };
+        let mut state = ButtonAxes::new();
+        assert_eq!(slew(&mut state, &config, 0, held(false, true)), ADC_MAX);
+        assert_eq!(slew(&mut state, &config, 1, held(false, false)), CENTER);
+
+        config.centering = false;
+        assert_eq!(slew(&mut state, &config, 2, held(true, false)), 0);
+        assert_eq!(slew(&mut state, &config, 3, held(false, false)), 0);
+        // Both held cancel out, leaving it where it is.
+        assert_eq!(slew(&mut state, &config, 4, held(true, true)), 0);
+    }
+
+    #[test]
+    fn used_button_axes_take_their_buttons() {
+        let config = [ButtonAxis::new(); BUTTON_AXIS_COUNT];
+        let mut state = ButtonAxes::new();
+        let mut buttons = held(true, true);
+        state.update(&config, &[false; BUTTON_AXIS_COUNT], 0, &mut buttons);
+        assert_eq!(buttons, held(true, true));
+        state.update(&config, &[false, true], 0, &mut buttons);
+        assert_eq!(buttons, held(false, false));
+    }
+
+    #[test]
+    fn rejects_missing_buttons_and_axes() {
+        assert!(AxisButton::new().is_valid());
+        let mut button = AxisButton::new();
+        button.button = BUTTON_COUNT as u8;
+        assert!(!button.is_valid());
+        let mut button = AxisButton::new();
+        button.axis = AXIS_COUNT as u8;
+        assert!(!button.is_valid());
+        let mut button = AxisButton::new();
+        button.threshold = i16::MIN;
+        assert!(!button.is_valid());
+
+        let mut axis = ButtonAxis::new();
+        assert!(axis.is_valid());
+        axis.buttons[1] = BUTTON_COUNT as u8;
+        assert!(!axis.is_valid());
+    }
+}
@@ -1,14 +1,37 @@
 use embassy_net::Stack;
-use embassy_time::Duration;
+use embassy_time::{Duration, Timer};
 use picoserve::{
     extract::{self, State},
+    io::{Read, Write},
     make_static,
-    response::{json, File, IntoResponse},
-    routing::{get, get_service, post},
-    AppRouter, AppWithStateBuilder, Config,
+    response::{
+        json,
+        ws::{SocketRx, SocketTx, WebSocketCallback, WebSocketUpgrade},
+        Connection, File, IntoResponse, ResponseWriter, StatusCode,
+    },
+    routing::{get, get_service, parse_path_segment, post, put},
+    AppRouter, AppWithStateBuilder, Config, ResponseSent,
+};
+use usb_joystick::{
+    axis::AXIS_COUNT,
+    button_modes::ButtonMode,
+    calibration::Capture,
+    config::{Config as DeviceConfig, ConfigError},
+    curves::Curve,
+    hid_descriptor::ReportLayout,
+    host_reports::LED_COUNT,
+    inputs::BUTTON_COUNT,
+    led_patterns::Pattern,
+    pixels::PixelConfig,
+    profiles::{Profile, ProfileName, PROFILE_COUNT},
+    sources::AxisSource,
 };
 
-use crate::state::{AppState, SharedStateMutex};
+use crate::{
+    leds::{self, LedCommand},
+    state::{AppState, SharedStateMutex, INPUT_UPDATES},
+    storage,
+};
 
 const INDEX_HTML: &str = include_str!("../static/index.html");
 const STYLE_CSS: &str = include_str!("../static/style.css");
@@ -16,10 +39,280 @@ const SCRIPT_JS: &str = include_str!("../static/script.js");
 
 pub struct AppProps;
 
+/// Sends a value as JSON, like [`json::Json`], but writes it out by
+/// reference. `json::Json` moves its value through every layer of the
+/// response, and a copy of a whole config at each one adds up to kilobytes
+/// in every web task.
+pub struct BigJson<T>(T);
+
+impl<T: serde::Serialize> IntoResponse for BigJson<T> {
+    async fn write_to<R: Read, W: ResponseWriter<Error = R::Error>>(
+        self,
+        connection: Connection<'_, R>,
+        response_writer: W,
+    ) -> Result<ResponseSent, W::Error> {
+        json::Json(&self.0)
+            .write_to(connection, response_writer)
+            .await
+    }
+}
+
 pub async fn get_state(
     extract::State(SharedStateMutex(shared)): extract::State<SharedStateMutex>,
 ) -> impl IntoResponse {
-    json::Json((*shared.lock().await).power)
+    json::Json(shared.lock().await.config.power)
+}
+
+pub async fn get_inputs(
+    extract::State(SharedStateMutex(shared)): extract::State<SharedStateMutex>,
+) -> impl IntoResponse {
+    json::Json(shared.lock().await.inputs)
+}
+
+pub async fn get_config(
+    extract::State(SharedStateMutex(shared)): extract::State<SharedStateMutex>,
+) -> impl IntoResponse {
+    BigJson(shared.lock().await.config)
+}
+
+/// Sources or encoders that add or remove axes are saved, but the report
+/// only gains or loses those axes when the device next starts. Until then,
+/// an added axis isn't sent and a removed one reads as centred.
+pub async fn put_config(
+    extract::State(SharedStateMutex(shared)): extract::State<SharedStateMutex>,
+    extract::Json(config): extract::Json<DeviceConfig>,
+) -> impl IntoResponse {
+    let state = &mut *shared.lock().await;
+    // Profiles have an API of their own, so they're kept as they are.
+    let config = DeviceConfig {
+        active_profile: state.config.active_profile,
+        profiles: state.config.profiles,
+        ..config
+    };
+    if let Err(e) = config.validate() {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, e.message()));
+    }
+    let previous = core::mem::replace(&mut state.config, config);
+    leds::config_changed(&previous, &config);
+    storage::request_save();
+    Ok(BigJson(config))
+}
+
+pub async fn get_leds(
+    extract::State(SharedStateMutex(shared)): extract::State<SharedStateMutex>,
+) -> impl IntoResponse {
+    json::Json(shared.lock().await.config.led_pattern)
+}
+
+pub async fn put_leds(
+    extract::State(SharedStateMutex(shared)): extract::State<SharedStateMutex>,
+    extract::Json(pattern): extract::Json<Pattern>,
+) -> impl IntoResponse {
+    if !pattern.is_valid() {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            ConfigError::LedPattern.message(),
+        ));
+    }
+    shared.lock().await.config.led_pattern = pattern;
+    leds::send(LedCommand::Pattern(pattern));
+    storage::request_save();
+    Ok(json::Json(pattern))
+}
+
+pub async fn get_brightness(
+    extract::State(SharedStateMutex(shared)): extract::State<SharedStateMutex>,
+) -> impl IntoResponse {
+    json::Json(shared.lock().await.config.led_brightness)
+}
+
+pub async fn put_brightness(
+    extract::State(SharedStateMutex(shared)): extract::State<SharedStateMutex>,
+    extract::Json(brightness): extract::Json<[u8; LED_COUNT]>,
+) -> impl IntoResponse {
+    // Any level is valid, and the LED task picks it up on its next frame.
+    shared.lock().await.config.led_brightness = brightness;
+    storage::request_save();
+    json::Json(brightness)
+}
+
+pub async fn get_pixels(
+    extract::State(SharedStateMutex(shared)): extract::State<SharedStateMutex>,
+) -> impl IntoResponse {
+    json::Json(shared.lock().await.config.pixels)
+}
+
+pub async fn put_pixels(
+    extract::State(SharedStateMutex(shared)): extract::State<SharedStateMutex>,
+    extract::Json(pixels): extract::Json<PixelConfig>,
+) -> impl IntoResponse {
+    if !pixels.is_valid() {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            ConfigError::Pixels.message(),
+        ));
+    }
+    shared.lock().await.config.pixels = pixels;
+    storage::request_save();
+    Ok(json::Json(pixels))
+}
+
+pub async fn get_axes(
+    extract::State(SharedStateMutex(shared)): extract::State<SharedStateMutex>,
+) -> impl IntoResponse {
+    json::Json(shared.lock().await.config.axis_sources)
+}
+
+/// Takes effect straight away, as the joystick reads the sources on every
+/// poll. The report descriptor can't change while the device is enumerated,
+/// so sources that would add or remove an axis are refused. Those can only
+/// be changed through [`put_config`].
+pub async fn put_axes(
+    extract::State(SharedStateMutex(shared)): extract::State<SharedStateMutex>,
+    extract::Json(sources): extract::Json<[AxisSource; AXIS_COUNT]>,
+) -> impl IntoResponse {
+    if let Some(axis) = sources.iter().position(|source| !source.is_valid()) {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            ConfigError::AxisSource(axis).message(),
+        ));
+    }
+    let state = &mut *shared.lock().await;
+    if ReportLayout::of(&sources, &state.config.encoders) != state.layout {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            ConfigError::ReportLayout.message(),
+        ));
+    }
+    state.config.axis_sources = sources;
+    storage::request_save();
+    Ok(json::Json(sources))
+}
+
+pub async fn get_curves(
+    extract::State(SharedStateMutex(shared)): extract::State<SharedStateMutex>,
+) -> impl IntoResponse {
+    json::Json(shared.lock().await.config.curves)
+}
+
+pub async fn put_curves(
+    extract::State(SharedStateMutex(shared)): extract::State<SharedStateMutex>,
+    extract::Json(curves): extract::Json<[Curve; AXIS_COUNT]>,
+) -> impl IntoResponse {
+    if let Some(axis) = curves.iter().position(|curve| !curve.is_valid()) {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            ConfigError::Curve(axis).message(),
+        ));
+    }
+    shared.lock().await.config.curves = curves;
+    storage::request_save();
+    Ok(json::Json(curves))
+}
+
+pub async fn get_button_modes(
+    extract::State(SharedStateMutex(shared)): extract::State<SharedStateMutex>,
+) -> impl IntoResponse {
+    json::Json(shared.lock().await.config.button_modes)
+}
+
+pub async fn put_button_modes(
+    extract::State(SharedStateMutex(shared)): extract::State<SharedStateMutex>,
+    extract::Json(modes): extract::Json<[ButtonMode; BUTTON_COUNT]>,
+) -> impl IntoResponse {
+    if let Some(button) = modes.iter().position(|mode| !mode.is_valid()) {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            ConfigError::ButtonMode(button).message(),
+        ));
+    }
+    shared.lock().await.config.button_modes = modes;
+    storage::request_save();
+    Ok(json::Json(modes))
+}
+
+#[derive(serde::Serialize)]
+struct ProfileList {
+    active: u8,
+    names: [ProfileName; PROFILE_COUNT],
+}
+
+pub async fn get_profiles(
+    extract::State(SharedStateMutex(shared)): extract::State<SharedStateMutex>,
+) -> impl IntoResponse {
+    let config = &shared.lock().await.config;
+    json::Json(ProfileList {
+        active: config.active_profile,
+        names: core::array::from_fn(|i| config.profile_name(i)),
+    })
+}
+
+pub async fn put_active_profile(
+    extract::State(SharedStateMutex(shared)): extract::State<SharedStateMutex>,
+    extract::Json(index): extract::Json<u8>,
+) -> impl IntoResponse {
+    let state = &mut *shared.lock().await;
+    let config = &mut state.config;
+    let previous = *config;
+    if let Err(e) = config.switch_profile(index as usize, &state.layout) {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, e.message()));
+    }
+    leds::config_changed(&previous, config);
+    storage::request_save();
+    Ok(json::Json(index))
+}
+
+pub async fn get_profile(
+    index: usize,
+    extract::State(SharedStateMutex(shared)): extract::State<SharedStateMutex>,
+) -> impl IntoResponse {
+    match shared.lock().await.config.profile(index) {
+        Some(profile) => Ok(BigJson(profile)),
+        None => Err((StatusCode::NOT_FOUND, "profile has not been saved yet")),
+    }
+}
+
+pub async fn put_profile(
+    index: usize,
+    extract::State(SharedStateMutex(shared)): extract::State<SharedStateMutex>,
+    extract::Json(profile): extract::Json<Profile>,
+) -> impl IntoResponse {
+    let state = &mut *shared.lock().await;
+    let config = &mut state.config;
+    let previous = *config;
+    if let Err(e) = config.set_profile(index, profile, &state.layout) {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, e.message()));
+    }
+    leds::config_changed(&previous, config);
+    storage::request_save();
+    Ok(BigJson(profile))
+}
+
+/// Shortest gap between two messages on an input stream. The joystick runs at
+/// 1 kHz, far faster than a browser can draw.
+const INPUT_STREAM_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Streams [`Inputs`](usb_joystick::inputs::Inputs) to a browser as JSON.
+struct InputStream;
+
+impl WebSocketCallback for InputStream {
+    async fn run<R: Read, W: Write<Error = R::Error>>(
+        self,
+        _rx: SocketRx<R>,
+        mut tx: SocketTx<W>,
+    ) -> Result<(), W::Error> {
+        let Some(mut updates) = INPUT_UPDATES.receiver() else {
+            return tx.close((1013, "Too many input streams")).await;
+        };
+
+        // The watch only holds the newest value, so waiting between messages
+        // drops the samples in between rather than falling behind. Nothing is
+        // read from the browser; a closed socket shows up as a write error.
+        loop {
+            tx.send_json(updates.changed().await).await?;
+            Timer::after(INPUT_STREAM_INTERVAL).await;
+        }
+    }
 }
 
 impl AppWithStateBuilder for AppProps {
@@ -32,16 +325,69 @@ impl AppWithStateBuilder for AppProps {
             .route("/style.css", get_service(File::css(STYLE_CSS)))
             .route("/script.js", get_service(File::javascript(SCRIPT_JS)))
             .route("/state", get(get_state))
+            .route("/api/config", get(get_config).put(put_config))
+            .route("/api/leds", get(get_leds).put(put_leds))
+            .route(
+                "/api/leds/brightness",
+                get(get_brightness).put(put_brightness),
+            )
+            .route("/api/pixels", get(get_pixels).put(put_pixels))
+            .route("/api/axes", get(get_axes).put(put_axes))
+            .route("/api/curves", get(get_curves).put(put_curves))
+            .route(
+                "/api/buttons/modes",
+                get(get_button_modes).put(put_button_modes),
+            )
+            .route("/api/profiles", get(get_profiles))
+            .route("/api/profiles/active", put(put_active_profile))
+            .route(
+                ("/api/profiles", parse_path_segment::<usize>()),
+                get(get_profile).put(put_profile),
+            )
+            .route("/inputs", get(get_inputs))
+            .route(
+                "/inputs/stream",
+                get(|upgrade: WebSocketUpgrade| async { upgrade.on_upgrade(InputStream) }),
+            )
             .route(
                 "/power",
                 post(
                     |State(SharedStateMutex(shared)): State<SharedStateMutex>| async {
-                        let power = &mut shared.lock().await.power;
+                        let power = &mut shared.lock().await.config.power;
                         *power = !*power;
+                        storage::request_save();
                         json::Json(*power)
                     },
                 ),
             )
+            .route(
+                "/calibration/start",
+                post(
+                    |State(SharedStateMutex(shared)): State<SharedStateMutex>| async {
+                        shared.lock().await.capture = Some(Capture::new());
+                        json::Json(true)
+                    },
+                ),
+            )
+            .route(
+                "/calibration/finish",
+                post(
+                    |State(SharedStateMutex(shared)): State<SharedStateMutex>| async {
+                        let state = &mut *shared.lock().await;
+                        let previous = state.config.calibration;
+                        let updated = match state.capture.take() {
+                            Some(capture) => capture.finish(&mut state.config.calibration),
+                            None => Default::default(),
+                        };
+                        // A stray finish, or a capture that changed nothing,
+                        // isn't worth a flash write.
+                        if state.config.calibration != previous {
+                            storage::request_save();
+                        }
+                        json::Json(updated)
+                    },
+                ),
+            )
     }
 }
 
@@ -64,7 +410,7 @@ pub fn make_web_app() -> (
     (app, config)
 }
 
-pub(crate) const WEB_TASK_POOL_SIZE: usize = 3;
+pub(crate) const WEB_TASK_POOL_SIZE: usize = 4;
 #[embassy_executor::task(pool_size = WEB_TASK_POOL_SIZE)]
 pub async fn web_task(
     id: usize,
@@ -76,7 +422,10 @@ pub async fn web_task(
     let port = 80;
     let mut tcp_rx_buffer = [0; 1024];
     let mut tcp_tx_buffer = [0; 1024];
-    let mut http_buffer = [0; 2048];
+    // Big enough for a config or a profile with every curve point in use,
+    // every button remapped and on turbo, and every axis button set, along
+    // with a browser's headers.
+    let mut http_buffer = [0; 7168];
 
     picoserve::listen_and_serve_with_state(
         id,
@@ -0,0 +1,101 @@
+use embassy_rp::{
+    clocks::clk_sys_freq,
+    dma::{AnyChannel, Channel},
+    interrupt::typelevel::Binding,
+    into_ref,
+    pio::{
+        Config, Direction, FifoJoin, Instance, InterruptHandler, Pio, PioPin, ShiftConfig,
+        ShiftDirection, StateMachine,
+    },
+    Peripheral, PeripheralRef,
+};
+use embassy_time::Timer;
+use fixed::types::U24F8;
+
+/// PIO cycles spent on each part of a bit: the rising edge that starts it,
+/// the data itself, and the low tail that ends it.
+const T1: u32 = 2;
+const T2: u32 = 5;
+const T3: u32 = 3;
+
+/// Bit rate of the WS2812 data line, in kHz.
+const BIT_RATE_KHZ: u32 = 800;
+
+/// Time to send one pixel's 24 bits, in microseconds.
+const WORD_US: u64 = 24 * 1000 / BIT_RATE_KHZ as u64;
+
+/// Time the data line must be held low for the pixels to latch their
+/// colours, in microseconds.
+const LATCH_US: u64 = 55;
+
+/// Drives a strip of WS2812 LEDs from state machine 0 of a PIO block.
+pub struct Ws2812<'d, P: Instance> {
+    sm: StateMachine<'d, P, 0>,
+    dma: PeripheralRef<'d, AnyChannel>,
+}
+
+impl<'d, P: Instance> Ws2812<'d, P> {
+    pub fn new(
+        pio: impl Peripheral<P = P> + 'd,
+        irqs: impl Binding<P::Interrupt, InterruptHandler<P>>,
+        dma: impl Peripheral<P = impl Channel> + 'd,
+        pin: impl PioPin,
+    ) -> Self {
+        into_ref!(dma);
+        let Pio {
+            mut common,
+            mut sm0,
+            ..
+        } = Pio::new(pio, irqs);
+
+        // Each bit is a short high pulse for a 0 and a long one for a 1, made
+        // with side-set so the timing doesn't depend on the CPU.
+        let program = pio_proc::pio_asm!(
+            ".side_set 1",
+            ".wrap_target",
+            "bitloop:",
+            "    out x, 1        side 0 [2]", // T3 - 1
+            "    jmp !x do_zero  side 1 [1]", // T1 - 1
+            "    jmp bitloop     side 1 [4]", // T2 - 1
+            "do_zero:",
+            "    nop             side 0 [4]", // T2 - 1
+            ".wrap",
+        );
+        let program = common.load_program(&program.program);
+        let pin = common.make_pio_pin(pin);
+        sm0.set_pin_dirs(Direction::Out, &[&pin]);
+
+        let mut config = Config::default();
+        config.use_program(&program, &[&pin]);
+        // In kHz, to keep the numbers inside U24F8.
+        let clock = U24F8::from_num(clk_sys_freq() / 1000);
+        config.clock_divider = clock / U24F8::from_num(BIT_RATE_KHZ * (T1 + T2 + T3));
+        config.fifo_join = FifoJoin::TxOnly;
+        config.shift_out = ShiftConfig {
+            auto_fill: true,
+            threshold: 24,
+            direction: ShiftDirection::Left,
+        };
+        sm0.set_config(&config);
+        sm0.set_enable(true);
+
+        Self {
+            sm: sm0,
+            dma: dma.map_into(),
+        }
+    }
+
+    /// Sends one word per pixel, as made by
+    /// [`Rgb::to_grb_word`](usb_joystick::pixels::Rgb::to_grb_word), and waits
+    /// for the strip to latch them.
+    pub async fn write(&mut self, words: &[u32]) {
+        if words.is_empty() {
+            return;
+        }
+        self.sm.tx().dma_push(self.dma.reborrow(), words).await;
+        // The DMA is done once the last word is in the FIFO, so give the state
+        // machine time to shift out what's left, plus the word it's on.
+        let queued = self.sm.tx().level() as u64 + 1;
+        Timer::after_micros(queued * WORD_US + LATCH_US).await;
+    }
+}
@@ -6,6 +6,629 @@ function checkState() {
     });
 }
 
+// Hat values count clockwise from up; anything past 7 is neutral.
+const HAT_DIRECTIONS = ["N", "NE", "E", "SE", "S", "SW", "W", "NW"];
+
+function showInputs(inputs) {
+  document.querySelectorAll(".axis .bar").forEach((bar, i) => {
+    // Axes run from -32767 to 32767; draw the bar out from the center.
+    const half = (Math.abs(inputs.axes[i]) / 32767) * 50;
+    bar.style.left = (inputs.axes[i] < 0 ? 50 - half : 50) + "%";
+    bar.style.width = half + "%";
+  });
+  document.querySelectorAll(".lamp").forEach((lamp, i) => {
+    lamp.classList.toggle("on", inputs.buttons[i]);
+  });
+  document.querySelector("#hat").textContent = HAT_DIRECTIONS[inputs.hat] ?? "-";
+}
+
+function showLamps(count) {
+  const lamps = Array.from({ length: count }, (_, i) => {
+    const lamp = document.createElement("span");
+    lamp.className = "lamp";
+    lamp.textContent = i + 1;
+    return lamp;
+  });
+  document.querySelector(".lamps").replaceChildren(...lamps);
+}
+
+function streamInputs() {
+  const url = new URL("./inputs/stream", window.location.href);
+  url.protocol = url.protocol === "https:" ? "wss:" : "ws:";
+
+  const socket = new WebSocket(url);
+  socket.onmessage = (event) => showInputs(JSON.parse(event.data));
+  socket.onclose = () => setTimeout(streamInputs, 1000);
+}
+
+let loadedConfig;
+
+// Settings with a section of their own, left out of the generic form.
+const OWN_SECTION = [
+  "led_pattern",
+  "led_brightness",
+  "pixels",
+  "axis_sources",
+  "curves",
+  "button_modes",
+  "active_profile",
+  "profiles",
+];
+
+// Builds inputs for every setting, named by their path in the config object.
+function configField(name, value) {
+  if (typeof value === "object") {
+    const fieldset = document.createElement("fieldset");
+    const legend = document.createElement("legend");
+    legend.textContent = name.split(".").pop();
+    fieldset.append(legend);
+    for (const [key, child] of Object.entries(value)) {
+      fieldset.append(configField(`${name}.${key}`, child));
+    }
+    return fieldset;
+  }
+
+  const input = document.createElement("input");
+  input.name = name;
+  input.className = "input";
+  if (typeof value === "boolean") {
+    input.type = "checkbox";
+    input.checked = value;
+  } else if (typeof value === "string") {
+    input.type = "text";
+    input.value = value;
+  } else {
+    input.type = "number";
+    input.value = value;
+  }
+  const label = document.createElement("label");
+  label.className = "field";
+  label.append(name.split(".").pop(), input);
+  return label;
+}
+
+function showConfig(config) {
+  loadedConfig = config;
+  const fields = document.querySelector("#configFields");
+  fields.replaceChildren(
+    ...Object.entries(config)
+      .filter(([key]) => !OWN_SECTION.includes(key))
+      .map(([key, value]) => configField(key, value))
+  );
+  document.querySelector("#boolToggle").checked = config.power;
+  showLedPattern(config.led_pattern);
+  showBrightness(config.led_brightness);
+  showPixels(config.pixels);
+  showAxisSources(config.axis_sources);
+  showCurves(config.curves);
+  showButtonModes(config.button_modes);
+}
+
+function showLedPattern(pattern) {
+  // Option values are the JSON for each pattern, e.g. {"AxisBar":1}.
+  document.querySelector("#ledPattern").value = JSON.stringify(pattern);
+}
+
+function setLedPattern(event) {
+  fetch("./api/leds", {
+    method: "PUT",
+    headers: { "Content-Type": "application/json" },
+    body: event.target.value,
+  })
+    .then((response) => response.json())
+    .then((pattern) => {
+      loadedConfig.led_pattern = pattern;
+      showLedPattern(pattern);
+    });
+}
+
+function brightnessSliders() {
+  return document.querySelectorAll("#ledBrightness input");
+}
+
+function showBrightness(brightness) {
+  brightnessSliders().forEach((slider, i) => {
+    slider.value = brightness[i];
+  });
+}
+
+function setBrightness() {
+  const brightness = Array.from(brightnessSliders(), (slider) =>
+    Number(slider.value)
+  );
+  fetch("./api/leds/brightness", {
+    method: "PUT",
+    headers: { "Content-Type": "application/json" },
+    body: JSON.stringify(brightness),
+  })
+    .then((response) => response.json())
+    .then((brightness) => {
+      loadedConfig.led_brightness = brightness;
+    });
+}
+
+const BUTTON_COUNT = 32;
+const LED_COUNT = 6;
+const AXIS_NAMES = ["X", "Y", "Z", "Rz"];
+
+// Sources of one kind, as JSON like {"Button":0}, with their labels.
+function indexedSources(kind, labels) {
+  return Object.fromEntries(
+    labels.map((label, i) => [JSON.stringify({ [kind]: i }), label])
+  );
+}
+
+function numbered(name, count) {
+  return Array.from({ length: count }, (_, i) => `${name} ${i + 1}`);
+}
+
+// What a pixel can follow, as the JSON for each source.
+const PIXEL_SOURCES = {
+  '"Fixed"': "Fixed",
+  ...indexedSources("Button", numbered("Button", BUTTON_COUNT)),
+  ...indexedSources("Axis", AXIS_NAMES),
+  ...indexedSources("Led", numbered("Status LED", LED_COUNT)),
+};
+
+function toHex({ r, g, b }) {
+  return "#" + [r, g, b].map((c) => c.toString(16).padStart(2, "0")).join("");
+}
+
+function fromHex(hex) {
+  const [r, g, b] = [1, 3, 5].map((i) => parseInt(hex.slice(i, i + 2), 16));
+  return { r, g, b };
+}
+
+function pixelRow(pixel, i) {
+  const row = document.createElement("div");
+  row.className = "pixel";
+
+  const source = document.createElement("select");
+  source.className = "input";
+  for (const [value, label] of Object.entries(PIXEL_SOURCES)) {
+    source.add(new Option(label, value));
+  }
+  source.value = JSON.stringify(pixel.source);
+
+  const colors = ["off", "on"].map((name) => {
+    const input = document.createElement("input");
+    input.type = "color";
+    input.name = name;
+    input.value = toHex(pixel[name]);
+    return input;
+  });
+
+  row.append(`${i + 1}`, source, ...colors);
+  return row;
+}
+
+function showPixels(pixels) {
+  document.querySelector("#pixelCount").value = pixels.count;
+  document
+    .querySelector("#pixelRows")
+    .replaceChildren(...pixels.pixels.map(pixelRow));
+}
+
+function savePixels(event) {
+  event.preventDefault();
+  const status = document.querySelector("#pixelStatus");
+  const pixels = {
+    count: Number(document.querySelector("#pixelCount").value),
+    pixels: Array.from(document.querySelectorAll(".pixel"), (row) => ({
+      source: JSON.parse(row.querySelector("select").value),
+      off: fromHex(row.querySelector("[name=off]").value),
+      on: fromHex(row.querySelector("[name=on]").value),
+    })),
+  };
+  fetch("./api/pixels", {
+    method: "PUT",
+    headers: { "Content-Type": "application/json" },
+    body: JSON.stringify(pixels),
+  }).then(async (response) => {
+    if (response.ok) {
+      loadedConfig.pixels = await response.json();
+      showPixels(loadedConfig.pixels);
+      status.textContent = "Saved";
+    } else {
+      status.textContent = await response.text();
+    }
+  });
+}
+
+// What can drive an axis, as the JSON for each source.
+const AXIS_SOURCES = {
+  '"None"': "Not in report",
+  '{"Adc":0}': "ADC 0 (GPIO 26)",
+  '{"Adc":1}': "ADC 1 (GPIO 27)",
+  '{"Adc":2}': "ADC 2 (GPIO 28)",
+  '{"Adc":3}': "ADC 3 (GPIO 29)",
+  '"Temperature"': "Temperature sensor",
+  '{"Sensor":0}': "I2C sensor 1",
+  '{"Sensor":1}': "I2C sensor 2",
+  '{"Sensor":2}': "I2C sensor 3",
+  '{"Sensor":3}': "I2C sensor 4",
+  '{"Encoder":0}': "Encoder 1",
+  '{"Encoder":1}': "Encoder 2",
+  '{"Buttons":0}': "Button axis 1",
+  '{"Buttons":1}': "Button axis 2",
+};
+
+function showAxisSources(sources) {
+  const rows = sources.map((source, i) => {
+    const select = document.createElement("select");
+    select.className = "input";
+    for (const [value, label] of Object.entries(AXIS_SOURCES)) {
+      select.add(new Option(label, value));
+    }
+    select.value = JSON.stringify(source);
+    const label = document.createElement("label");
+    label.className = "field";
+    label.append(AXIS_NAMES[i], select);
+    return label;
+  });
+  document.querySelector("#axisSources").replaceChildren(...rows);
+}
+
+// Whether each axis is in the report with these sources.
+function presentAxes(sources) {
+  return sources.map((source) => JSON.stringify(source) !== '"None"').join();
+}
+
+function saveAxisSources(event) {
+  event.preventDefault();
+  const status = document.querySelector("#axisStatus");
+  const sources = Array.from(
+    document.querySelectorAll("#axisSources select"),
+    (select) => JSON.parse(select.value)
+  );
+  // Moving an axis to another input applies straight away, but adding or
+  // removing one changes the descriptor, which the host only reads when the
+  // device is plugged in. ./api/axes refuses that, so it goes in with the
+  // whole config instead.
+  const resize =
+    presentAxes(sources) !== presentAxes(loadedConfig.axis_sources);
+  const [url, body] = resize
+    ? [
+        "./api/config",
+        { ...loadedConfig, profiles: undefined, axis_sources: sources },
+      ]
+    : ["./api/axes", sources];
+  fetch(url, {
+    method: "PUT",
+    headers: { "Content-Type": "application/json" },
+    body: JSON.stringify(body),
+  }).then(async (response) => {
+    if (response.ok) {
+      const saved = await response.json();
+      loadedConfig.axis_sources = resize ? saved.axis_sources : saved;
+      showAxisSources(loadedConfig.axis_sources);
+      status.textContent = resize ? "Saved, restart to apply" : "Saved";
+    } else {
+      status.textContent = await response.text();
+    }
+  });
+}
+
+const AXIS_MAX = 32767;
+const MAX_CURVE_POINTS = 16;
+
+// The same shapes as src/curves.rs, on values from -1 to 1.
+function applyCurve(curve, x) {
+  const blend = (strength, shape) =>
+    Math.sign(x) *
+    ((Math.abs(x) * (100 - strength) + shape(Math.abs(x)) * strength) / 100);
+  if (curve.Expo !== undefined) {
+    return blend(curve.Expo, (t) => t * t * t);
+  }
+  if (curve.SCurve !== undefined) {
+    return blend(curve.SCurve, (t) => 3 * t * t - 2 * t * t * t);
+  }
+  if (curve.Points !== undefined) {
+    const points = curve.Points.points
+      .slice(0, curve.Points.count)
+      .map((p) => [p.input / AXIS_MAX, p.output / AXIS_MAX]);
+    const last = points[points.length - 1];
+    if (x <= points[0][0]) return points[0][1];
+    if (x >= last[0]) return last[1];
+    const i = points.findIndex(([input]) => input > x);
+    const [[x0, y0], [x1, y1]] = [points[i - 1], points[i]];
+    return y0 + ((y1 - y0) * (x - x0)) / (x1 - x0);
+  }
+  return x;
+}
+
+function plotCurve(canvas, curve) {
+  const { width, height } = canvas;
+  const context = canvas.getContext("2d");
+  context.clearRect(0, 0, width, height);
+  context.strokeStyle = "#444444";
+  context.beginPath();
+  context.moveTo(width / 2, 0);
+  context.lineTo(width / 2, height);
+  context.moveTo(0, height / 2);
+  context.lineTo(width, height / 2);
+  context.stroke();
+
+  context.strokeStyle = "#31f35b";
+  context.beginPath();
+  for (let px = 0; px <= width; px++) {
+    const y = applyCurve(curve, (px / width) * 2 - 1);
+    context.lineTo(px, ((1 - y) / 2) * height);
+  }
+  context.stroke();
+}
+
+// Points are edited as "input:output" pairs in percent of travel.
+function pointsText(points) {
+  return points.points
+    .slice(0, points.count)
+    .map((p) =>
+      [p.input, p.output].map((v) => Math.round((v / AXIS_MAX) * 100)).join(":")
+    )
+    .join(" ");
+}
+
+function parsePoints(text) {
+  const points = text
+    .trim()
+    .split(/\s+/)
+    .map((pair) => {
+      const [input, output] = pair
+        .split(":")
+        .map((v) => Math.round((Number(v) / 100) * AXIS_MAX));
+      return { input, output };
+    });
+  const count = points.length;
+  while (points.length < MAX_CURVE_POINTS) {
+    points.push(points[points.length - 1]);
+  }
+  return { count, points };
+}
+
+function readCurve(row) {
+  const kind = row.querySelector("select").value;
+  const strength = Number(row.querySelector("[name=strength]").value);
+  switch (kind) {
+    case "Expo":
+    case "SCurve":
+      return { [kind]: strength };
+    case "Points":
+      return { Points: parsePoints(row.querySelector("[name=points]").value) };
+    default:
+      return "Linear";
+  }
+}
+
+function curveRow(curve, i) {
+  const row = document.createElement("div");
+  row.className = "curve";
+
+  const kind = document.createElement("select");
+  kind.className = "input";
+  kind.add(new Option("Linear", "Linear"));
+  kind.add(new Option("Expo", "Expo"));
+  kind.add(new Option("S-curve", "SCurve"));
+  kind.add(new Option("Points", "Points"));
+  kind.value = typeof curve === "string" ? curve : Object.keys(curve)[0];
+
+  const strength = document.createElement("input");
+  strength.type = "number";
+  strength.name = "strength";
+  strength.className = "input";
+  strength.min = 0;
+  strength.max = 100;
+  strength.value = curve.Expo ?? curve.SCurve ?? 50;
+
+  const points = document.createElement("input");
+  points.type = "text";
+  points.name = "points";
+  points.className = "input points";
+  points.value = curve.Points
+    ? pointsText(curve.Points)
+    : "-100:-100 0:0 100:100";
+
+  const canvas = document.createElement("canvas");
+  canvas.width = 80;
+  canvas.height = 80;
+
+  const update = () => {
+    strength.hidden = !["Expo", "SCurve"].includes(kind.value);
+    points.hidden = kind.value !== "Points";
+    plotCurve(canvas, readCurve(row));
+  };
+  row.addEventListener("input", update);
+
+  const label = document.createElement("span");
+  label.className = "label";
+  label.textContent = AXIS_NAMES[i];
+  row.append(label, kind, strength, points, canvas);
+  update();
+  return row;
+}
+
+function showCurves(curves) {
+  document.querySelector("#curves").replaceChildren(...curves.map(curveRow));
+}
+
+function saveCurves(event) {
+  event.preventDefault();
+  const status = document.querySelector("#curveStatus");
+  const curves = Array.from(document.querySelectorAll(".curve"), readCurve);
+  fetch("./api/curves", {
+    method: "PUT",
+    headers: { "Content-Type": "application/json" },
+    body: JSON.stringify(curves),
+  }).then(async (response) => {
+    if (response.ok) {
+      loadedConfig.curves = await response.json();
+      showCurves(loadedConfig.curves);
+      status.textContent = "Saved";
+    } else {
+      status.textContent = await response.text();
+    }
+  });
+}
+
+function readButtonMode(row) {
+  const kind = row.querySelector("select").value;
+  const value = Number(row.querySelector("input").value);
+  return kind === "Turbo" || kind === "Pulse" ? { [kind]: value } : kind;
+}
+
+function buttonModeRow(mode, i) {
+  const row = document.createElement("div");
+  row.className = "button-mode";
+
+  const kind = document.createElement("select");
+  kind.className = "input";
+  kind.add(new Option("Normal", "Normal"));
+  kind.add(new Option("Toggle", "Toggle"));
+  kind.add(new Option("Turbo (Hz)", "Turbo"));
+  kind.add(new Option("Pulse (ms)", "Pulse"));
+  kind.value = typeof mode === "string" ? mode : Object.keys(mode)[0];
+
+  const value = document.createElement("input");
+  value.type = "number";
+  value.className = "input";
+  value.min = 1;
+  value.value = mode.Turbo ?? mode.Pulse ?? 10;
+
+  const update = () => {
+    value.hidden = !["Turbo", "Pulse"].includes(kind.value);
+    value.max = kind.value === "Turbo" ? 50 : 255;
+  };
+  kind.addEventListener("change", update);
+
+  const label = document.createElement("span");
+  label.className = "label";
+  label.textContent = i + 1;
+  row.append(label, kind, value);
+  update();
+  return row;
+}
+
+function showButtonModes(modes) {
+  document
+    .querySelector("#buttonModes")
+    .replaceChildren(...modes.map(buttonModeRow));
+}
+
+function saveButtonModes(event) {
+  event.preventDefault();
+  const status = document.querySelector("#buttonModeStatus");
+  const modes = Array.from(document.querySelectorAll(".button-mode"), readButtonMode);
+  fetch("./api/buttons/modes", {
+    method: "PUT",
+    headers: { "Content-Type": "application/json" },
+    body: JSON.stringify(modes),
+  }).then(async (response) => {
+    if (response.ok) {
+      loadedConfig.button_modes = await response.json();
+      showButtonModes(loadedConfig.button_modes);
+      status.textContent = "Saved";
+    } else {
+      status.textContent = await response.text();
+    }
+  });
+}
+
+function showProfiles({ active, names }) {
+  const select = document.querySelector("#activeProfile");
+  select.replaceChildren(...names.map((name, i) => new Option(name, i)));
+  select.value = active;
+  document.querySelector("#profileName").value = names[active];
+}
+
+function loadProfiles() {
+  fetch("./api/profiles")
+    .then((response) => response.json())
+    .then(showProfiles);
+}
+
+function switchProfile(event) {
+  const status = document.querySelector("#profileStatus");
+  fetch("./api/profiles/active", {
+    method: "PUT",
+    headers: { "Content-Type": "application/json" },
+    body: event.target.value,
+  }).then(async (response) => {
+    if (response.ok) {
+      // Every other section shows settings from the new profile.
+      fetch("./api/config")
+        .then((response) => response.json())
+        .then(showConfig);
+      loadProfiles();
+      status.textContent = "Switched";
+    } else {
+      // Put the selection back on the profile still in use.
+      loadProfiles();
+      status.textContent = await response.text();
+    }
+  });
+}
+
+function renameProfile(event) {
+  event.preventDefault();
+  const status = document.querySelector("#profileStatus");
+  const url = `./api/profiles/${document.querySelector("#activeProfile").value}`;
+  fetch(url)
+    .then((response) => response.json())
+    .then((profile) =>
+      fetch(url, {
+        method: "PUT",
+        headers: { "Content-Type": "application/json" },
+        body: JSON.stringify({
+          ...profile,
+          name: document.querySelector("#profileName").value,
+        }),
+      })
+    )
+    .then(async (response) => {
+      if (response.ok) {
+        loadProfiles();
+        status.textContent = "Saved";
+      } else {
+        status.textContent = await response.text();
+      }
+    });
+}
+
+function readConfigForm() {
+  const config = structuredClone(loadedConfig);
+  // Left alone by the device, and too big to send back.
+  delete config.profiles;
+  for (const input of document.querySelectorAll("#configFields input")) {
+    const path = input.name.split(".");
+    const key = path.pop();
+    const parent = path.reduce((object, step) => object[step], config);
+    if (input.type === "checkbox") {
+      parent[key] = input.checked;
+    } else if (input.type === "text") {
+      parent[key] = input.value;
+    } else {
+      parent[key] = Number(input.value);
+    }
+  }
+  return config;
+}
+
+function saveConfig(event) {
+  event.preventDefault();
+  const status = document.querySelector("#configStatus");
+  fetch("./api/config", {
+    method: "PUT",
+    headers: { "Content-Type": "application/json" },
+    body: JSON.stringify(readConfigForm()),
+  }).then(async (response) => {
+    if (response.ok) {
+      showConfig(await response.json());
+      status.textContent = "Saved";
+    } else {
+      status.textContent = await response.text();
+    }
+  });
+}
+
 function debounce_leading(func, timeout = 300) {
   let timer;
   return (...args) => {
@@ -25,7 +648,7 @@ document.addEventListener("DOMContentLoaded", function () {
   document.querySelector("#boolToggle").addEventListener(
     "click",
     debounce_leading(function () {
-      fetch("./toggle", { method: "POST" })
+      fetch("./power", { method: "POST" })
         .then((a) => a.json())
         .then((state) => {
           document.querySelector("#boolToggle").checked = state;
@@ -34,6 +657,28 @@ document.addEventListener("DOMContentLoaded", function () {
     })
   );
 
+  // One lamp per button in the report, whether or not it is wired up.
+  showLamps(BUTTON_COUNT);
+  streamInputs();
+
+  fetch("./api/config")
+    .then((response) => response.json())
+    .then(showConfig);
+  document.querySelector("#configForm").addEventListener("submit", saveConfig);
+  document.querySelector("#ledPattern").addEventListener("change", setLedPattern);
+  document.querySelector("#pixelForm").addEventListener("submit", savePixels);
+  document.querySelector("#axisForm").addEventListener("submit", saveAxisSources);
+  document.querySelector("#curveForm").addEventListener("submit", saveCurves);
+  document
+    .querySelector("#buttonModeForm")
+    .addEventListener("submit", saveButtonModes);
+  loadProfiles();
+  document.querySelector("#activeProfile").addEventListener("change", switchProfile);
+  document.querySelector("#profileForm").addEventListener("submit", renameProfile);
+  brightnessSliders().forEach((slider) =>
+    slider.addEventListener("change", setBrightness)
+  );
+
   let params = new URLSearchParams(window.location.search);
   if (params.has("watch")) {
     setInterval(checkState, 1000);